@@ -0,0 +1,80 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use ue3_config_parser::parse::{split_lines, Directives, Span};
+
+/// Build a config of roughly `size` bytes with a mix of headers, comments,
+/// KVPs, multiline values, and `\r\n` line endings.
+fn synthetic_config(size: usize) -> String {
+    let mut text = String::with_capacity(size + 256);
+    let mut i = 0;
+    while text.len() < size {
+        text.push_str(&format!(
+            "; Section {}\r\n[Package{}.Class{}]\r\n",
+            i,
+            i % 7,
+            i
+        ));
+        for k in 0..8 {
+            text.push_str(&format!("Key{}=Value{}\r\n", k, i * k));
+        }
+        text.push_str("+Entries=(Name=\"Item\", Count=3, \\\\\r\n    Flags=(A, B, C))\r\n\r\n");
+        i += 1;
+    }
+    text
+}
+
+/// The line splitter before it used `memchr`, for comparison.
+fn split_lines_char_predicate(text: &str) -> Vec<Span> {
+    let mut lines = vec![];
+    let mut remaining = text;
+    let mut offset = 0;
+    while !remaining.is_empty() {
+        match remaining.find(['\r', '\n']) {
+            Some(p) => {
+                lines.push(Span(offset, offset + p));
+                offset += p;
+                remaining = &remaining[p..];
+                while remaining.starts_with(['\r', '\n']) {
+                    offset += 1;
+                    remaining = &remaining[1..];
+                }
+            }
+            None => {
+                lines.push(Span(offset, offset + remaining.len()));
+                break;
+            }
+        }
+    }
+    lines
+}
+
+fn bench_split_lines(c: &mut Criterion) {
+    let text = synthetic_config(4 << 20);
+    assert_eq!(split_lines(&text), split_lines_char_predicate(&text));
+
+    let mut group = c.benchmark_group("split_lines");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.bench_with_input(BenchmarkId::new("memchr", text.len()), &text, |b, text| {
+        b.iter(|| split_lines(black_box(text)))
+    });
+    group.bench_with_input(
+        BenchmarkId::new("char_predicate", text.len()),
+        &text,
+        |b, text| b.iter(|| split_lines_char_predicate(black_box(text))),
+    );
+    group.finish();
+}
+
+fn bench_from_text(c: &mut Criterion) {
+    let text = synthetic_config(4 << 20);
+
+    let mut group = c.benchmark_group("from_text");
+    group.throughput(Throughput::Bytes(text.len() as u64));
+    group.sample_size(20);
+    group.bench_function("4MiB", |b| {
+        b.iter(|| Directives::from_text(black_box(&text)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_split_lines, bench_from_text);
+criterion_main!(benches);
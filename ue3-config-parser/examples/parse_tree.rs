@@ -1,7 +1,6 @@
-use std::fs::read_to_string;
-use std::io;
+use std::fs::read;
 
-use ue3_config_parser::check::SimpleSyntaxValidator;
+use ue3_config_parser::{check::SimpleSyntaxValidator, parse::Directives};
 use walkdir::{DirEntry, WalkDir};
 
 fn is_ini(entry: &DirEntry) -> bool {
@@ -28,22 +27,25 @@ fn main() {
             continue;
         }
 
-        let contents = match read_to_string(entry.path()) {
+        let contents = match read(entry.path()) {
             Ok(c) => c,
-            Err(e) if e.kind() == io::ErrorKind::InvalidData => {
-                println!("{:?}: Invalid UTF-8", entry.path());
-                continue;
-            }
             Err(e) => {
                 println!("{:?}: I/O Error {:?}", entry.path(), e);
                 continue;
             }
         };
+        if std::str::from_utf8(&contents).is_err() {
+            println!(
+                "{:?}: Invalid UTF-8, spans refer to the decoded text",
+                entry.path()
+            );
+        }
 
-        let d = ue3_config_parser::parse::Directives::from_text(&contents);
+        let owned = Directives::from_bytes_lossy(&contents);
+        let d = owned.as_directives();
         for u in &d.validate(&SimpleSyntaxValidator) {
             println!("{:?}: {:?} {:?}", entry.path(), u.kind, u.span);
-            println!("{}", &(*contents)[u.span]);
+            println!("{}", &d.text[u.span]);
         }
     }
 }
@@ -0,0 +1,217 @@
+use std::fs::{self, read_to_string};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use ue3_config_parser::{
+    check::{Severity, SimpleSyntaxValidator},
+    parse::{Directives, LineIndex},
+};
+
+const USAGE: &str = "usage: ue3-config-lint [--warnings-as-errors] [--format text|json] <PATH>...
+
+Lints UE3 config files. Directories are searched recursively for `.ini` files,
+and `-` reads from stdin.";
+
+enum Format {
+    Text,
+    Json,
+}
+
+struct Options {
+    warnings_as_errors: bool,
+    format: Format,
+    paths: Vec<String>,
+}
+
+struct Diagnostic {
+    file: String,
+    line: u32,
+    col: u32,
+    end_line: u32,
+    end_col: u32,
+    code: &'static str,
+    severity: Severity,
+    message: String,
+}
+
+fn parse_args() -> Options {
+    let mut opts = Options {
+        warnings_as_errors: false,
+        format: Format::Text,
+        paths: vec![],
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match &*arg {
+            "--warnings-as-errors" => opts.warnings_as_errors = true,
+            "--format" => match args.next().as_deref() {
+                Some("text") => opts.format = Format::Text,
+                Some("json") => opts.format = Format::Json,
+                _ => {
+                    eprintln!("`--format` must be `text` or `json`\n\n{}", USAGE);
+                    process::exit(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            "-" => opts.paths.push(arg),
+            _ if arg.starts_with('-') => {
+                eprintln!("unknown option `{}`\n\n{}", arg, USAGE);
+                process::exit(2);
+            }
+            _ => opts.paths.push(arg),
+        }
+    }
+
+    if opts.paths.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    opts
+}
+
+fn is_ini(path: &Path) -> bool {
+    path.extension().map(|e| e == "ini").unwrap_or(false)
+}
+
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> io::Result<()> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|e| e.map(|e| e.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            if entry.is_dir() {
+                collect_files(&entry, files)?;
+            } else if is_ini(&entry) {
+                files.push(entry);
+            }
+        }
+    } else {
+        files.push(path.to_owned());
+    }
+    Ok(())
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn print_json(diags: &[Diagnostic]) {
+    println!("[");
+    for (i, d) in diags.iter().enumerate() {
+        println!(
+            "  {{\"file\":{},\"line\":{},\"col\":{},\"end_line\":{},\"end_col\":{},\"code\":{},\"severity\":{},\"message\":{}}}{}",
+            json_string(&d.file),
+            d.line,
+            d.col,
+            d.end_line,
+            d.end_col,
+            json_string(d.code),
+            json_string(d.severity.as_str()),
+            json_string(&d.message),
+            if i + 1 < diags.len() { "," } else { "" }
+        );
+    }
+    println!("]");
+}
+
+fn print_text(diags: &[Diagnostic]) {
+    for d in diags {
+        println!(
+            "{}:{}:{}: {}[{}]: {}",
+            d.file,
+            d.line,
+            d.col,
+            d.severity.as_str(),
+            d.code,
+            d.message
+        );
+    }
+}
+
+fn lint(name: &str, contents: &str, diags: &mut Vec<Diagnostic>) {
+    let directives = Directives::from_text(contents);
+    let index = LineIndex::new(contents);
+    for e in directives.validate(&SimpleSyntaxValidator) {
+        let (line, col) = index.line_col(e.span.0);
+        let (end_line, end_col) = index.line_col(e.span.1);
+        diags.push(Diagnostic {
+            file: name.to_owned(),
+            line,
+            col,
+            end_line,
+            end_col,
+            code: e.kind.code(),
+            severity: e.severity,
+            message: e.kind.message().to_owned(),
+        });
+    }
+}
+
+fn main() {
+    let opts = parse_args();
+    let mut failed = false;
+    let mut diags = vec![];
+
+    for path in &opts.paths {
+        if path == "-" {
+            let mut contents = String::new();
+            match io::stdin().read_to_string(&mut contents) {
+                Ok(_) => lint("<stdin>", &contents, &mut diags),
+                Err(e) => {
+                    eprintln!("<stdin>: {}", e);
+                    failed = true;
+                }
+            }
+            continue;
+        }
+
+        let mut files = vec![];
+        if let Err(e) = collect_files(Path::new(path), &mut files) {
+            eprintln!("{}: {}", path, e);
+            failed = true;
+        }
+
+        for file in files {
+            match read_to_string(&file) {
+                Ok(contents) => lint(&file.display().to_string(), &contents, &mut diags),
+                Err(e) => {
+                    eprintln!("{}: {}", file.display(), e);
+                    failed = true;
+                }
+            }
+        }
+    }
+
+    match opts.format {
+        Format::Text => print_text(&diags),
+        Format::Json => print_json(&diags),
+    }
+
+    failed |= diags
+        .iter()
+        .any(|d| d.severity == Severity::Error || opts.warnings_as_errors);
+    if failed {
+        process::exit(1);
+    }
+}
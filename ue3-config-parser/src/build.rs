@@ -0,0 +1,199 @@
+use crate::{
+    check::{escape_value, needs_quoting, struct_syntax},
+    parse::{
+        split_lines, Directive, Kvp, KvpOperation, OwnedDirectives, SectionHeader, Span, Unknown,
+    },
+};
+
+/// Assembles [`OwnedDirectives`] line by line, without parsing any text.
+///
+/// Every call adds one directive and its line. The result is the same as
+/// parsing the generated text with [`crate::parse::ParseOptions::keep_blank_lines`],
+/// since [`DirectivesBuilder::blank`] adds a [`Directive::Blank`].
+///
+/// ```
+/// use ue3_config_parser::{build::DirectivesBuilder, parse::KvpOperation};
+///
+/// let directives = DirectivesBuilder::new()
+///     .header("XComGame.X2Item")
+///     .kvp(KvpOperation::InsertUnique, "Names", "Hello World")
+///     .build();
+/// assert_eq!(directives.text, "[XComGame.X2Item]\n+Names=\"Hello World\"\n");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct DirectivesBuilder {
+    text: String,
+    directives: Vec<Directive>,
+    /// The last line that isn't blank, for [`Unknown::prev_span`].
+    last_line: Option<Span>,
+}
+
+impl DirectivesBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a `[name]` section header.
+    pub fn header(mut self, name: &str) -> Self {
+        let start = self.text.len();
+        self.text.push('[');
+        self.text.push_str(name);
+        self.text.push(']');
+        let span = Span(start, self.text.len());
+        self.directives
+            .push(Directive::SectionHeader(SectionHeader {
+                span,
+                trimmed: span,
+                obj_name: Span(start + 1, span.1 - 1),
+                comment: None,
+            }));
+        self.end_line(span)
+    }
+
+    /// Add a KVP. The key is written as is, while the value is quoted and escaped
+    /// with [`escape_value`] unless it is empty, a quoted string, a struct, or
+    /// an array, or doesn't need quoting in the first place.
+    pub fn kvp(mut self, op: KvpOperation, key: &str, value: &str) -> Self {
+        let start = self.text.len();
+        match op {
+            KvpOperation::Set => {}
+            KvpOperation::Insert => self.text.push('.'),
+            KvpOperation::InsertUnique => self.text.push('+'),
+            KvpOperation::Remove => self.text.push('-'),
+            KvpOperation::Clear => self.text.push('!'),
+        }
+        let ident = Span(self.text.len(), self.text.len() + key.len());
+        self.text.push_str(key);
+        self.text.push('=');
+
+        let is_literal = value.is_empty()
+            || !needs_quoting(value)
+            || matches!(
+                struct_syntax::parse_value(value),
+                Ok(struct_syntax::PropValue::Str(_)
+                    | struct_syntax::PropValue::Struct(_)
+                    | struct_syntax::PropValue::Array(_)
+                    | struct_syntax::PropValue::Empty)
+            );
+        let value = if is_literal {
+            value.into()
+        } else {
+            escape_value(value)
+        };
+        let value_span = Span(self.text.len(), self.text.len() + value.len());
+        self.text.push_str(&value);
+
+        let lines = split_lines(&value);
+        self.directives.push(Directive::Kvp(Kvp {
+            span: Span(ident.0, value_span.1),
+            ident,
+            value: value_span,
+            op,
+            comment: None,
+            line_count: lines.len().max(1) as u32,
+        }));
+        // The last line of a multiline value
+        let last_start = match lines.last() {
+            Some(l) if lines.len() > 1 => value_span.0 + l.0,
+            _ => start,
+        };
+        self.end_line(Span(last_start, value_span.1))
+    }
+
+    /// Add a comment line. A `;` is put in front of `text` unless it already
+    /// starts with one. `text` must not contain line breaks.
+    pub fn comment(mut self, text: &str) -> Self {
+        let start = self.text.len();
+        if !text.starts_with(';') {
+            self.text.push_str("; ");
+        }
+        self.text.push_str(text);
+        let span = Span(start, self.text.len());
+        self.directives.push(Directive::Unknown(Unknown {
+            span,
+            prev_span: self.last_line,
+        }));
+        self.end_line(span)
+    }
+
+    /// Add an empty line.
+    pub fn blank(mut self) -> Self {
+        let pos = self.text.len();
+        self.directives.push(Directive::Blank(Span(pos, pos)));
+        self.text.push('\n');
+        self
+    }
+
+    pub fn build(self) -> OwnedDirectives {
+        OwnedDirectives {
+            text: self.text,
+            directives: self.directives,
+        }
+    }
+
+    fn end_line(mut self, last_line: Span) -> Self {
+        self.text.push('\n');
+        self.last_line = Some(last_line);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::DirectivesBuilder;
+    use crate::parse::{Directives, KvpOperation, ParseOptions};
+
+    #[test]
+    fn two_sections() {
+        let built = DirectivesBuilder::new()
+            .comment("Generated")
+            .header("XComGame.X2Item")
+            .kvp(KvpOperation::Set, "Name", "Hello World")
+            .kvp(KvpOperation::Clear, "Costs", "")
+            .kvp(
+                KvpOperation::InsertUnique,
+                "Costs",
+                "(Name=Supplies, Quantity=25)",
+            )
+            .kvp(KvpOperation::Insert, "Lines", "First\nSecond")
+            .comment("; after a multiline value")
+            .blank()
+            .header("XComGame.X2Ability")
+            .kvp(KvpOperation::Remove, "Path", r#"C:\Program Files"#)
+            .kvp(KvpOperation::Set, "Quoted", r#""Already quoted""#)
+            .kvp(KvpOperation::Set, "Count", "3")
+            .build();
+
+        let expected = expect![[r#"
+            ; Generated
+            [XComGame.X2Item]
+            Name="Hello World"
+            !Costs=
+            +Costs=(Name=Supplies, Quantity=25)
+            .Lines="First
+            Second"
+            ; after a multiline value
+
+            [XComGame.X2Ability]
+            -Path="C:\\Program Files"
+            Quoted="Already quoted"
+            Count=3
+        "#]];
+        expected.assert_eq(&built.text);
+
+        let options = ParseOptions {
+            keep_blank_lines: true,
+            ..Default::default()
+        };
+        let reparsed = Directives::from_text_with_options(&built.text, &options);
+        assert_eq!(reparsed.directives, built.directives);
+        assert_eq!(
+            reparsed.resolved_value("XComGame.X2Item", "Name"),
+            built
+                .as_directives()
+                .resolved_value("XComGame.X2Item", "Name")
+        );
+    }
+}
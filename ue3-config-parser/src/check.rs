@@ -1,21 +1,122 @@
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::ControlFlow,
+};
+
+#[cfg(feature = "regex")]
 use once_cell::sync::Lazy;
+#[cfg(feature = "regex")]
 use regex::Regex;
 
-use crate::parse::{Directive, Directives, Kvp, KvpOperation, SectionHeader, Span, Unknown};
+use crate::parse::{
+    join_continuations, split_lines, Directive, Directives, Kvp, KvpOperation, LineIndex,
+    SectionHeader, Span, Unknown,
+};
+
+#[cfg(not(feature = "regex"))]
+mod scan;
+pub(crate) mod struct_syntax;
 
-mod struct_syntax;
+#[cfg(not(feature = "regex"))]
+use scan::{IDENT, KEY, OBJECT};
 
+pub use struct_syntax::{
+    escape_value, needs_quoting, tokenize, tokenize_spanned, tokenize_spanned_with, tokenize_with,
+    unescape_quoted, LexOptions, StructVisitor, Token,
+};
+
+#[cfg(feature = "regex")]
 static KEY: Lazy<Regex> = Lazy::new(|| {
     Regex::new(r"^[A-Za-z][A-Za-z0-9_]*(\[(0|[1-9][0-9]*)\]|\((0|[1-9][0-9]*)\))?$").unwrap()
 });
 
+#[cfg(feature = "regex")]
 static OBJECT: Lazy<Regex> =
     Lazy::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9_]*([ \.][A-Za-z][A-Za-z0-9_]*)?$").unwrap());
 
+#[cfg(feature = "regex")]
 static IDENT: Lazy<Regex> = Lazy::new(|| Regex::new(r"^[A-Za-z][A-Za-z0-9_]*$").unwrap());
 
 pub trait Validator {
-    fn visit_section_header(&self, text: &str, span: &Span) -> DiagResult;
+    fn visit_section_header(&self, _text: &str, _span: &Span) -> DiagResult {
+        DiagResult::None
+    }
+    fn visit_kvp(
+        &self,
+        _op: KvpOperation,
+        _prop: &str,
+        _prop_span: &Span,
+        _text: &str,
+        _text_span: &Span,
+    ) -> DiagResult {
+        DiagResult::None
+    }
+    fn visit_unknown(&self, _text: &str, _span: &Span) -> DiagResult {
+        DiagResult::None
+    }
+    /// Called for every directive after the visit method for its kind, with
+    /// access to the whole text and the surrounding directives. For checks of
+    /// the raw lines, like the whitespace around a header.
+    fn visit_directive(&self, _directives: &Directives<'_>, _index: usize) -> DiagResult {
+        DiagResult::None
+    }
+    /// Called before the first directive of every validated input, so that
+    /// validators keeping track of earlier directives can start over.
+    fn reset(&self) {}
+}
+
+type SectionHeaderFn<'f> = Box<dyn Fn(&str, &Span) -> DiagResult + 'f>;
+type KvpFn<'f> = Box<dyn Fn(KvpOperation, &str, &Span, &str, &Span) -> DiagResult + 'f>;
+type UnknownFn<'f> = Box<dyn Fn(&str, &Span) -> DiagResult + 'f>;
+type DirectiveFn<'f> = Box<dyn Fn(&Directives<'_>, usize) -> DiagResult + 'f>;
+
+/// A [`Validator`] built from closures. Visits without a closure return [`DiagResult::None`].
+#[derive(Default)]
+pub struct FnValidator<'f> {
+    section_header: Option<SectionHeaderFn<'f>>,
+    kvp: Option<KvpFn<'f>>,
+    unknown: Option<UnknownFn<'f>>,
+    directive: Option<DirectiveFn<'f>>,
+}
+
+impl<'f> FnValidator<'f> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn section_header(mut self, f: impl Fn(&str, &Span) -> DiagResult + 'f) -> Self {
+        self.section_header = Some(Box::new(f));
+        self
+    }
+
+    pub fn kvp(
+        mut self,
+        f: impl Fn(KvpOperation, &str, &Span, &str, &Span) -> DiagResult + 'f,
+    ) -> Self {
+        self.kvp = Some(Box::new(f));
+        self
+    }
+
+    pub fn unknown(mut self, f: impl Fn(&str, &Span) -> DiagResult + 'f) -> Self {
+        self.unknown = Some(Box::new(f));
+        self
+    }
+
+    pub fn directive(mut self, f: impl Fn(&Directives<'_>, usize) -> DiagResult + 'f) -> Self {
+        self.directive = Some(Box::new(f));
+        self
+    }
+}
+
+impl<'f> Validator for FnValidator<'f> {
+    fn visit_section_header(&self, text: &str, span: &Span) -> DiagResult {
+        match &self.section_header {
+            Some(f) => f(text, span),
+            None => DiagResult::None,
+        }
+    }
+
     fn visit_kvp(
         &self,
         op: KvpOperation,
@@ -23,8 +124,26 @@ pub trait Validator {
         prop_span: &Span,
         text: &str,
         text_span: &Span,
-    ) -> DiagResult;
-    fn visit_unknown(&self, text: &str, span: &Span) -> DiagResult;
+    ) -> DiagResult {
+        match &self.kvp {
+            Some(f) => f(op, prop, prop_span, text, text_span),
+            None => DiagResult::None,
+        }
+    }
+
+    fn visit_unknown(&self, text: &str, span: &Span) -> DiagResult {
+        match &self.unknown {
+            Some(f) => f(text, span),
+            None => DiagResult::None,
+        }
+    }
+
+    fn visit_directive(&self, directives: &Directives<'_>, index: usize) -> DiagResult {
+        match &self.directive {
+            Some(f) => f(directives, index),
+            None => DiagResult::None,
+        }
+    }
 }
 
 pub struct SimpleSyntaxValidator;
@@ -33,10 +152,34 @@ impl Validator for SimpleSyntaxValidator {
     fn visit_section_header(&self, text: &str, span: &Span) -> DiagResult {
         if OBJECT.is_match(text) {
             DiagResult::Ok
+        } else if text.is_empty() {
+            // Blame the brackets of `[]` rather than the empty span between them
+            DiagResult::Err(vec![ReportedError {
+                kind: ErrorKind::Custom {
+                    code: "empty-section-name",
+                    msg: "empty section name".to_owned(),
+                },
+                span: Span(span.0.saturating_sub(1), span.1 + 1),
+                severity: Severity::Error,
+                related: None,
+            }])
+        } else if let Some((pos, c)) = first_invalid_object_char(text) {
+            let start = span.0 + pos;
+            DiagResult::Err(vec![ReportedError {
+                kind: ErrorKind::Custom {
+                    code: "invalid-section-char",
+                    msg: format!("unexpected character `{}` in section name", c),
+                },
+                span: Span(start, start + c.len_utf8()),
+                severity: Severity::Error,
+                related: None,
+            }])
         } else {
             DiagResult::Err(vec![ReportedError {
                 kind: ErrorKind::InvalidIdent,
                 span: *span,
+                severity: Severity::Error,
+                related: None,
             }])
         }
     }
@@ -53,9 +196,23 @@ impl Validator for SimpleSyntaxValidator {
         if !KEY.is_match(prop) {
             match try_report_comment(prop, prop_span) {
                 DiagResult::Ok => return DiagResult::Ok,
+                // The operation has already been stripped, so this is e.g. `++Key=x`
+                DiagResult::None if prop.starts_with(['+', '.', '-', '!']) => {
+                    errs.push(ReportedError {
+                        span: *prop_span,
+                        kind: ErrorKind::Custom {
+                            code: "extra-operation",
+                            msg: "unexpected operation character in key".to_owned(),
+                        },
+                        severity: Severity::Error,
+                        related: None,
+                    })
+                }
                 DiagResult::None => errs.push(ReportedError {
                     span: *prop_span,
                     kind: ErrorKind::InvalidIdent,
+                    severity: Severity::Error,
+                    related: None,
                 }),
                 DiagResult::Err(e) => {
                     errs.extend(e);
@@ -78,102 +235,972 @@ impl Validator for SimpleSyntaxValidator {
     }
 
     fn visit_unknown(&self, text: &str, span: &Span) -> DiagResult {
-        let mut errs = vec![];
-        match try_report_comment(text, span) {
-            DiagResult::Ok => return DiagResult::Ok,
-            DiagResult::None => {}
-            DiagResult::Err(e) => {
-                errs.extend(e);
+        let kind = match classify_line(text) {
+            LineClass::Comment => return DiagResult::Ok,
+            LineClass::SlashComment => ErrorKind::SlashSlashComent,
+            LineClass::MalformedHeader => ErrorKind::MalformedHeader,
+            LineClass::MissingEquals => ErrorKind::Custom {
+                code: "missing-equals",
+                msg: "operation without '='".to_owned(),
+            },
+            LineClass::Other => ErrorKind::Other,
+        };
+        DiagResult::Err(vec![ReportedError {
+            kind,
+            span: *span,
+            severity: Severity::Error,
+            related: None,
+        }])
+    }
+
+    fn visit_directive(&self, directives: &Directives<'_>, index: usize) -> DiagResult {
+        match &directives.directives[index] {
+            Directive::SectionHeader(SectionHeader {
+                span,
+                trimmed,
+                comment,
+                ..
+            }) => {
+                // Whitespace before a trailing comment is fine
+                if span.0 != trimmed.0 || (comment.is_none() && span.1 != trimmed.1) {
+                    return DiagResult::Err(vec![ReportedError {
+                        span: *span,
+                        kind: ErrorKind::MalformedHeader,
+                        severity: Severity::Error,
+                        related: None,
+                    }]);
+                }
+                DiagResult::Ok
+            }
+            Directive::Kvp(Kvp { value, .. }) => {
+                // Whitespace after the backslashes ends the value
+                let value_text = &directives.text[value];
+                let last_line = value_text.rsplit(['\r', '\n']).next().unwrap_or_default();
+                let trimmed = last_line.trim_end();
+                if trimmed.len() == last_line.len() || !trimmed.ends_with(r"\\") {
+                    return DiagResult::Ok;
+                }
+                let beg = value.1 - last_line.len() + trimmed.len() - 2;
+
+                // If the next line is an error, `Directives::validate` already
+                // reports it as the line that was meant to be continued
+                let next = directives.directives[index + 1..]
+                    .iter()
+                    .find(|d| !matches!(d, Directive::Blank(_)));
+                if let Some(Directive::Unknown(Unknown {
+                    span,
+                    prev_span: Some(prev_span),
+                })) = next
+                {
+                    if prev_span.0 <= beg
+                        && beg < prev_span.1
+                        && matches!(
+                            self.visit_unknown(&directives.text[span], span),
+                            DiagResult::Err(_)
+                        )
+                    {
+                        return DiagResult::Ok;
+                    }
+                }
+                DiagResult::Err(vec![ReportedError {
+                    span: Span(beg, value.1),
+                    kind: ErrorKind::SpaceAfterMultiline,
+                    severity: Severity::Error,
+                    related: Some(Span(beg, beg + 2)),
+                }])
+            }
+            _ => DiagResult::None,
+        }
+    }
+}
+
+/// Runs several [`Validator`]s and combines their results.
+#[derive(Default)]
+pub struct CompositeValidator<'v> {
+    validators: Vec<Box<dyn Validator + 'v>>,
+}
+
+impl<'v> CompositeValidator<'v> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, validator: impl Validator + 'v) -> Self {
+        self.validators.push(Box::new(validator));
+        self
+    }
+
+    fn combine(&self, visit: impl Fn(&dyn Validator) -> DiagResult) -> DiagResult {
+        self.validators
+            .iter()
+            .fold(DiagResult::None, |acc, v| acc.merge(visit(&**v)))
+    }
+}
+
+impl<'v> Validator for CompositeValidator<'v> {
+    fn visit_section_header(&self, text: &str, span: &Span) -> DiagResult {
+        self.combine(|v| v.visit_section_header(text, span))
+    }
+
+    fn visit_kvp(
+        &self,
+        op: KvpOperation,
+        prop: &str,
+        prop_span: &Span,
+        text: &str,
+        text_span: &Span,
+    ) -> DiagResult {
+        self.combine(|v| v.visit_kvp(op, prop, prop_span, text, text_span))
+    }
+
+    fn visit_unknown(&self, text: &str, span: &Span) -> DiagResult {
+        self.combine(|v| v.visit_unknown(text, span))
+    }
+
+    fn visit_directive(&self, directives: &Directives<'_>, index: usize) -> DiagResult {
+        self.combine(|v| v.visit_directive(directives, index))
+    }
+
+    fn reset(&self) {
+        for v in &self.validators {
+            v.reset();
+        }
+    }
+}
+
+/// Reports lines that are indented with both tabs and spaces, including every
+/// line of a multiline value.
+pub struct WhitespaceValidator;
+
+impl Validator for WhitespaceValidator {
+    fn visit_directive(&self, directives: &Directives<'_>, index: usize) -> DiagResult {
+        let errs = directive_lines(directives, index)
+            .filter_map(|line| {
+                let text = &directives.text[line];
+                let indent = text.len() - text.trim_start_matches([' ', '\t']).len();
+                (text[..indent].contains(' ') && text[..indent].contains('\t')).then(|| {
+                    ReportedError {
+                        kind: ErrorKind::Custom {
+                            code: "mixed-indentation",
+                            msg: "mixed tabs and spaces".to_owned(),
+                        },
+                        span: Span(line.0, line.0 + indent),
+                        severity: Severity::Warning,
+                        related: None,
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+
+        if errs.is_empty() {
+            DiagResult::None
+        } else {
+            DiagResult::Err(errs)
+        }
+    }
+}
+
+/// The physical lines of the directive at `index`, from the start of its first
+/// line, so including any indentation. Blank directives have none.
+fn directive_lines<'d>(
+    directives: &'d Directives<'_>,
+    index: usize,
+) -> impl Iterator<Item = Span> + 'd {
+    let span = match &directives.directives[index] {
+        Directive::Blank(_) => Span(0, 0),
+        d => d.span(),
+    };
+    let start = directives.text[..span.0]
+        .rfind(['\r', '\n'])
+        .map_or(0, |p| p + 1);
+    split_lines(&directives.text[start..span.1])
+        .into_iter()
+        .map(move |l| Span(start + l.0, start + l.1))
+}
+
+/// Reports keys that are set more than once in the same section. Since UE3 ignores
+/// case, keys that differ only by case are reported as well. Repeated sections are
+/// merged, so a key set again in a later `[Same.Section]` is reported too.
+///
+/// Only `Set` operations are considered, since repeating array operations is normal.
+#[derive(Default)]
+pub struct DuplicateKeyValidator {
+    /// The lowercase name of the current section, empty before the first header.
+    section: RefCell<String>,
+    /// Maps the lowercase section and key to the spelling the key was first set with.
+    seen: RefCell<HashMap<(String, String), String>>,
+}
+
+impl DuplicateKeyValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Validator for DuplicateKeyValidator {
+    fn visit_section_header(&self, text: &str, _span: &Span) -> DiagResult {
+        *self.section.borrow_mut() = text.to_ascii_lowercase();
+        DiagResult::None
+    }
+
+    fn visit_kvp(
+        &self,
+        op: KvpOperation,
+        prop: &str,
+        prop_span: &Span,
+        _text: &str,
+        _text_span: &Span,
+    ) -> DiagResult {
+        if op != KvpOperation::Set {
+            return DiagResult::None;
+        }
+        let mut seen = self.seen.borrow_mut();
+        let key = (self.section.borrow().clone(), prop.to_ascii_lowercase());
+        let msg = match seen.get(&key) {
+            None => {
+                seen.insert(key, prop.to_owned());
+                return DiagResult::None;
             }
+            Some(first) if first == prop => format!("duplicate key `{}`", prop),
+            Some(first) => format!("key `{}` differs only by case from `{}`", prop, first),
+        };
+        DiagResult::Err(vec![ReportedError {
+            kind: ErrorKind::Custom {
+                code: "duplicate-key",
+                msg,
+            },
+            span: *prop_span,
+            severity: Severity::Warning,
+            related: None,
+        }])
+    }
+
+    fn reset(&self) {
+        self.section.borrow_mut().clear();
+        self.seen.borrow_mut().clear();
+    }
+}
+
+/// Warns about section headers that appear more than once in a file, ignoring case.
+/// UE3 merges such sections, but a repeated header is usually an accidental paste.
+#[derive(Default)]
+pub struct DuplicateSectionValidator {
+    /// The lowercase names of the sections seen so far.
+    seen: RefCell<HashSet<String>>,
+}
+
+impl DuplicateSectionValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Validator for DuplicateSectionValidator {
+    fn visit_section_header(&self, text: &str, span: &Span) -> DiagResult {
+        if self.seen.borrow_mut().insert(text.to_ascii_lowercase()) {
+            return DiagResult::None;
         }
+        DiagResult::Err(vec![ReportedError {
+            kind: ErrorKind::Custom {
+                code: "duplicate-section",
+                msg: "duplicate section header".to_owned(),
+            },
+            span: *span,
+            severity: Severity::Warning,
+            related: None,
+        }])
+    }
+
+    fn reset(&self) {
+        self.seen.borrow_mut().clear();
+    }
+}
 
-        match try_report_section_error(text, span) {
-            DiagResult::Ok => return DiagResult::Ok,
-            DiagResult::None => {}
-            DiagResult::Err(e) => {
-                errs.extend(e);
+/// Warns about section headers that are a single name instead of `Package.Class`,
+/// which is usually a forgotten class name. Not part of [`SimpleSyntaxValidator`],
+/// since UE3 does accept such sections.
+pub struct BareSectionValidator;
+
+impl Validator for BareSectionValidator {
+    fn visit_section_header(&self, text: &str, span: &Span) -> DiagResult {
+        if text.contains('.') || !OBJECT.is_match(text) {
+            return DiagResult::None;
+        }
+        DiagResult::Err(vec![ReportedError {
+            kind: ErrorKind::Custom {
+                code: "bare-section",
+                msg: "section header has no package.class form".to_owned(),
+            },
+            span: *span,
+            severity: Severity::Warning,
+            related: None,
+        }])
+    }
+}
+
+/// Warns about `Set` operations without a value, e.g. `Name=`, which is usually a
+/// forgotten value. Other operations are fine without a value, e.g. `!Array=`.
+pub struct EmptyValueValidator;
+
+impl Validator for EmptyValueValidator {
+    fn visit_kvp(
+        &self,
+        op: KvpOperation,
+        _prop: &str,
+        prop_span: &Span,
+        text: &str,
+        _text_span: &Span,
+    ) -> DiagResult {
+        if op != KvpOperation::Set || !text.trim().is_empty() {
+            return DiagResult::None;
+        }
+        DiagResult::Err(vec![ReportedError {
+            kind: ErrorKind::Custom {
+                code: "empty-value",
+                msg: "empty value".to_owned(),
+            },
+            span: *prop_span,
+            severity: Severity::Warning,
+            related: None,
+        }])
+    }
+}
+
+/// Warns about `+` and `.` operations whose value is an array of several elements,
+/// e.g. `+Items=(A, B)`. Such an operation adds a single array-valued element,
+/// while separate operations for `A` and `B` were most likely intended.
+pub struct ArrayElementValidator;
+
+impl Validator for ArrayElementValidator {
+    fn visit_kvp(
+        &self,
+        op: KvpOperation,
+        _prop: &str,
+        _prop_span: &Span,
+        text: &str,
+        text_span: &Span,
+    ) -> DiagResult {
+        if !matches!(op, KvpOperation::InsertUnique | KvpOperation::Insert) {
+            return DiagResult::None;
+        }
+        match struct_syntax::parse_value(text.trim()) {
+            Ok(struct_syntax::PropValue::Array(a)) if a.len() > 1 => {
+                DiagResult::Err(vec![ReportedError {
+                    kind: ErrorKind::Custom {
+                        code: "array-insert",
+                        msg: format!(
+                    "value is an array of {} elements, but this operation adds a single element",
+                    a.len()
+                ),
+                    },
+                    span: *text_span,
+                    severity: Severity::Warning,
+                    related: None,
+                }])
             }
+            _ => DiagResult::None,
         }
+    }
+}
+
+/// Warns about unquoted names with whitespace in them inside struct and array
+/// values, e.g. `(Name=Hello World)`. The engine may stop reading the name at
+/// the whitespace, so such names should be quoted. Top-level values like
+/// `Name=Hello World` are read up to the end of the line and are not reported.
+pub struct UnquotedWhitespaceValidator;
 
+impl UnquotedWhitespaceValidator {
+    fn collect<'t>(value: &struct_syntax::PropValue<'t>, names: &mut Vec<&'t str>) {
+        match value {
+            struct_syntax::PropValue::Name(s) if s.trim_end().contains([' ', '\t']) => {
+                names.push(s.trim_end())
+            }
+            struct_syntax::PropValue::Struct(s) => {
+                for (_, v) in &s.children {
+                    Self::collect(v, names);
+                }
+            }
+            struct_syntax::PropValue::Array(a) => {
+                for v in &a.elems {
+                    Self::collect(v, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Validator for UnquotedWhitespaceValidator {
+    fn visit_kvp(
+        &self,
+        _op: KvpOperation,
+        _prop: &str,
+        _prop_span: &Span,
+        text: &str,
+        text_span: &Span,
+    ) -> DiagResult {
+        let value = match struct_syntax::parse_value(text.trim()) {
+            Ok(v @ (struct_syntax::PropValue::Struct(_) | struct_syntax::PropValue::Array(_))) => v,
+            _ => return DiagResult::None,
+        };
+        let mut names = vec![];
+        Self::collect(&value, &mut names);
+        if names.is_empty() {
+            return DiagResult::None;
+        }
+        DiagResult::Err(
+            names
+                .into_iter()
+                .map(|name| {
+                    // `name` borrows from `text`
+                    let start = text_span.0 + (name.as_ptr() as usize - text.as_ptr() as usize);
+                    ReportedError {
+                        kind: ErrorKind::Custom {
+                            code: "unquoted-whitespace",
+                            msg: format!(
+                                "unquoted value `{}` contains whitespace, consider quoting it",
+                                name
+                            ),
+                        },
+                        span: Span(start, start + name.len()),
+                        severity: Severity::Warning,
+                        related: None,
+                    }
+                })
+                .collect(),
+        )
+    }
+}
+
+/// Reports control characters in KVP values, which are usually left over from
+/// exporting binary data and break the engine even if editors hide them. Tabs
+/// and the line breaks of multiline values are allowed.
+pub struct ControlCharValidator;
+
+impl Validator for ControlCharValidator {
+    fn visit_kvp(
+        &self,
+        _op: KvpOperation,
+        _prop: &str,
+        _prop_span: &Span,
+        text: &str,
+        text_span: &Span,
+    ) -> DiagResult {
+        let errs = text
+            .char_indices()
+            .filter(|&(_, c)| c.is_control() && !matches!(c, '\t' | '\r' | '\n'))
+            .map(|(i, c)| ReportedError {
+                kind: ErrorKind::Custom {
+                    code: "control-character",
+                    msg: "control character in value".to_owned(),
+                },
+                span: Span(text_span.0 + i, text_span.0 + i + c.len_utf8()),
+                severity: Severity::Error,
+                related: None,
+            })
+            .collect::<Vec<_>>();
         if errs.is_empty() {
-            DiagResult::Err(vec![ReportedError {
-                kind: ErrorKind::Other,
-                span: *span,
-            }])
+            DiagResult::None
+        } else {
+            DiagResult::Err(errs)
+        }
+    }
+}
+
+/// Warns about physical lines longer than `limit` characters, which some tools
+/// reading config files can't handle. The lines of multiline KVPs are measured
+/// separately. Whole source lines are measured, including indentation and
+/// trailing comments.
+pub struct MaxLineLengthValidator {
+    pub limit: usize,
+}
+
+impl Validator for MaxLineLengthValidator {
+    fn visit_directive(&self, directives: &Directives<'_>, index: usize) -> DiagResult {
+        let errs = directive_lines(directives, index)
+            .filter(|&line| directives.text[line].chars().count() > self.limit)
+            .map(|line| ReportedError {
+                kind: ErrorKind::Custom {
+                    code: "line-too-long",
+                    msg: format!("line exceeds {} characters", self.limit),
+                },
+                span: line,
+                severity: Severity::Warning,
+                related: None,
+            })
+            .collect::<Vec<_>>();
+        if errs.is_empty() {
+            DiagResult::None
         } else {
             DiagResult::Err(errs)
         }
     }
 }
 
+/// Reports `U+FFFD` replacement characters anywhere in a directive. They are
+/// usually left by [`Directives::from_bytes_lossy`] or an editor decoding a file
+/// that isn't UTF-8, so the text being checked isn't what the engine will read.
+pub struct ReplacementCharValidator;
+
+impl ReplacementCharValidator {
+    fn report(text: &str, start: usize) -> DiagResult {
+        let errs = text
+            .match_indices('\u{FFFD}')
+            .map(|(i, c)| ReportedError {
+                kind: ErrorKind::Custom {
+                    code: "replacement-character",
+                    msg: "possible encoding error (replacement character)".to_owned(),
+                },
+                span: Span(start + i, start + i + c.len()),
+                severity: Severity::Warning,
+                related: None,
+            })
+            .collect::<Vec<_>>();
+        if errs.is_empty() {
+            DiagResult::None
+        } else {
+            DiagResult::Err(errs)
+        }
+    }
+}
+
+impl Validator for ReplacementCharValidator {
+    fn visit_section_header(&self, text: &str, span: &Span) -> DiagResult {
+        Self::report(text, span.0)
+    }
+
+    fn visit_kvp(
+        &self,
+        _op: KvpOperation,
+        prop: &str,
+        prop_span: &Span,
+        text: &str,
+        text_span: &Span,
+    ) -> DiagResult {
+        Self::report(prop, prop_span.0).merge(Self::report(text, text_span.0))
+    }
+
+    fn visit_unknown(&self, text: &str, span: &Span) -> DiagResult {
+        Self::report(text, span.0)
+    }
+}
+
+/// Recognizes lines pasted from an UnrealScript `defaultproperties` block and
+/// suggests the config syntax instead, since [`SimpleSyntaxValidator`] can only
+/// report most of them as [`ErrorKind::Other`] or as an invalid key. Recognized are:
+///
+/// * `Begin Object` and `End Object` lines,
+/// * the `defaultproperties` keyword and lines with a single `{` or `}`,
+/// * dynamic array functions like `Arr.Add(X)`,
+/// * values ending with `;`,
+/// * properties before the first section header.
+#[derive(Default)]
+pub struct DefaultPropertiesValidator {
+    seen_section: Cell<bool>,
+}
+
+impl DefaultPropertiesValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn hint(msg: &str, span: Span) -> DiagResult {
+        DiagResult::Err(vec![ReportedError {
+            kind: ErrorKind::Custom {
+                code: "pasted-defaultproperties",
+                msg: msg.to_owned(),
+            },
+            span,
+            severity: Severity::Warning,
+            related: None,
+        }])
+    }
+}
+
+const SUBOBJECT_HINT: &str =
+    "`Begin Object`/`End Object` blocks only work in defaultproperties, not in config files";
+
+impl Validator for DefaultPropertiesValidator {
+    fn visit_section_header(&self, _text: &str, _span: &Span) -> DiagResult {
+        self.seen_section.set(true);
+        DiagResult::None
+    }
+
+    fn visit_kvp(
+        &self,
+        _op: KvpOperation,
+        prop: &str,
+        prop_span: &Span,
+        text: &str,
+        text_span: &Span,
+    ) -> DiagResult {
+        let span = Span(prop_span.0, text_span.1);
+        if starts_with_words(prop, &["Begin", "Object"]) {
+            Self::hint(SUBOBJECT_HINT, span)
+        } else if !self.seen_section.get() {
+            Self::hint(
+                "property before the first section header, config properties belong in a `[Package.Class]` section",
+                span,
+            )
+        } else if text.trim_end().ends_with(';') {
+            Self::hint(
+                "config values don't end with `;`, remove it if this was copied from defaultproperties",
+                *text_span,
+            )
+        } else {
+            DiagResult::None
+        }
+    }
+
+    fn visit_unknown(&self, text: &str, span: &Span) -> DiagResult {
+        let line = text.trim();
+        if starts_with_words(line, &["Begin", "Object"])
+            || starts_with_words(line, &["End", "Object"])
+        {
+            Self::hint(SUBOBJECT_HINT, *span)
+        } else if line.eq_ignore_ascii_case("defaultproperties") || line == "{" || line == "}" {
+            Self::hint(
+                "looks like a defaultproperties block, config files use `[Package.Class]` sections instead",
+                *span,
+            )
+        } else if is_array_function(line) {
+            Self::hint(
+                "array functions don't work in config files, use `+Key=Value` or `-Key=Value` instead",
+                *span,
+            )
+        } else {
+            DiagResult::None
+        }
+    }
+
+    fn reset(&self) {
+        self.seen_section.set(false);
+    }
+}
+
+/// Whether `s` starts with `words` separated by whitespace, ignoring case.
+fn starts_with_words(s: &str, words: &[&str]) -> bool {
+    let mut parts = s.split_whitespace();
+    words
+        .iter()
+        .all(|w| parts.next().is_some_and(|p| p.eq_ignore_ascii_case(w)))
+}
+
+/// Whether `line` is a call like `Arr.Add(X)` or `Arr.Empty()`.
+fn is_array_function(line: &str) -> bool {
+    let call = match line.split_once('.') {
+        Some((name, call)) if IDENT.is_match(name) => call.to_ascii_lowercase(),
+        _ => return false,
+    };
+    [
+        "add(",
+        "additem(",
+        "remove(",
+        "removeitem(",
+        "insert(",
+        "empty(",
+    ]
+    .iter()
+    .any(|f| call.starts_with(f))
+        && call.trim_end_matches(';').ends_with(')')
+}
+
+/// Reports `+` operations that insert a value that was already inserted with `+`
+/// for the same key in the same section, including repeated headers of that section.
+/// Values are compared after trimming and processing escape sequences, so `+Arr=X`
+/// and `+Arr="X"` are duplicates.
+///
+/// `.` operations are not considered, since they are meant to allow duplicates.
+#[derive(Default)]
+pub struct DuplicateInsertValidator {
+    /// The lowercase name of the current section, empty before the first header.
+    section: RefCell<String>,
+    /// Maps the lowercase section and base key to the values inserted with `+`.
+    seen: RefCell<HashMap<(String, String), HashSet<String>>>,
+}
+
+impl DuplicateInsertValidator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Validator for DuplicateInsertValidator {
+    fn visit_section_header(&self, text: &str, _span: &Span) -> DiagResult {
+        *self.section.borrow_mut() = text.to_ascii_lowercase();
+        DiagResult::None
+    }
+
+    fn visit_kvp(
+        &self,
+        op: KvpOperation,
+        prop: &str,
+        prop_span: &Span,
+        text: &str,
+        _text_span: &Span,
+    ) -> DiagResult {
+        if op != KvpOperation::InsertUnique {
+            return DiagResult::None;
+        }
+        let base_key = prop.find(['[', '(']).map_or(prop, |p| &prop[..p]);
+        let value = text.trim();
+        let value = match struct_syntax::parse_value(value) {
+            Ok(v) => v
+                .content()
+                .map_or_else(|| value.to_owned(), |c| c.into_owned()),
+            Err(_) => value.to_owned(),
+        };
+        let is_new = self
+            .seen
+            .borrow_mut()
+            .entry((self.section.borrow().clone(), base_key.to_ascii_lowercase()))
+            .or_default()
+            .insert(value);
+        if is_new {
+            return DiagResult::None;
+        }
+        DiagResult::Err(vec![ReportedError {
+            kind: ErrorKind::Custom {
+                code: "duplicate-insert",
+                msg: "duplicate unique insert".to_owned(),
+            },
+            span: *prop_span,
+            severity: Severity::Warning,
+            related: None,
+        }])
+    }
+
+    fn reset(&self) {
+        self.section.borrow_mut().clear();
+        self.seen.borrow_mut().clear();
+    }
+}
+
+/// Validates many inputs with the same validator, reusing one buffer for the
+/// errors instead of allocating a new `Vec` for every [`Directives::validate`].
+/// Meant for long-running processes like editors that revalidate on every change.
+pub struct ValidationContext<'v> {
+    checker: &'v (dyn Validator + 'v),
+    errs: Vec<ReportedError>,
+}
+
+impl<'v> ValidationContext<'v> {
+    pub fn new(checker: &'v (dyn Validator + 'v)) -> Self {
+        Self {
+            checker,
+            errs: vec![],
+        }
+    }
+
+    /// Validate `directives`, replacing the errors of the previous call.
+    /// Returns the same errors as [`Directives::validate`].
+    pub fn validate_into(&mut self, directives: &Directives<'_>) -> &[ReportedError] {
+        self.errs.clear();
+        self.checker.reset();
+        for index in 0..directives.directives.len() {
+            directives.validate_into(index, self.checker, &mut self.errs);
+        }
+        &self.errs
+    }
+
+    /// The errors of the last [`ValidationContext::validate_into`] call.
+    pub fn errors(&self) -> &[ReportedError] {
+        &self.errs
+    }
+}
+
 impl<'a> Directives<'a> {
     pub fn validate(&self, checker: &(dyn Validator + '_)) -> Vec<ReportedError> {
         let mut errs = vec![];
-        for d in &self.directives {
-            match d {
-                Directive::SectionHeader(SectionHeader { span: _, obj_name }) => {
-                    match checker.visit_section_header(&self.text[obj_name], obj_name) {
-                        DiagResult::Ok | DiagResult::None => {}
-                        DiagResult::Err(e) => errs.extend(e),
-                    }
+        self.validate_with_sink(checker, |e| {
+            errs.push(e);
+            ControlFlow::Continue(())
+        });
+        errs
+    }
+
+    /// Like `validate`, but stops after `max` errors have been collected
+    /// without visiting the remaining directives.
+    pub fn validate_limited(
+        &self,
+        checker: &(dyn Validator + '_),
+        max: usize,
+    ) -> Vec<ReportedError> {
+        let mut errs = vec![];
+        if max == 0 {
+            return errs;
+        }
+        self.validate_with_sink(checker, |e| {
+            errs.push(e);
+            if errs.len() >= max {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        errs
+    }
+
+    /// Like `validate`, but passes every error to `sink` as soon as its directive
+    /// has been checked instead of collecting them. Once `sink` returns
+    /// [`ControlFlow::Break`], no further errors are reported and the remaining
+    /// directives aren't visited.
+    pub fn validate_with_sink<F: FnMut(ReportedError) -> ControlFlow<()>>(
+        &self,
+        checker: &(dyn Validator + '_),
+        mut sink: F,
+    ) {
+        let mut errs = vec![];
+        checker.reset();
+        for index in 0..self.directives.len() {
+            self.validate_into(index, checker, &mut errs);
+            for e in errs.drain(..) {
+                if sink(e).is_break() {
+                    return;
                 }
-                Directive::Kvp(Kvp {
-                    span: _,
-                    ident,
-                    value,
-                    op,
-                }) => {
-                    match checker.visit_kvp(*op, &self.text[ident], ident, &self.text[value], value)
-                    {
-                        DiagResult::Ok | DiagResult::None => {}
-                        DiagResult::Err(e) => errs.extend(e),
-                    }
+            }
+        }
+    }
+
+    /// Validate only the directive at `index`. For validators that don't keep state
+    /// across directives, this returns the same errors as [`Directives::validate`]
+    /// does for this directive.
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn validate_directive(
+        &self,
+        index: usize,
+        checker: &(dyn Validator + '_),
+    ) -> Vec<ReportedError> {
+        let mut errs = vec![];
+        self.validate_into(index, checker, &mut errs);
+        errs
+    }
+
+    fn validate_into(
+        &self,
+        index: usize,
+        checker: &(dyn Validator + '_),
+        errs: &mut Vec<ReportedError>,
+    ) {
+        match &self.directives[index] {
+            Directive::SectionHeader(SectionHeader { obj_name, .. }) => {
+                match checker.visit_section_header(&self.text[obj_name], obj_name) {
+                    DiagResult::Ok | DiagResult::None => {}
+                    DiagResult::Err(e) => errs.extend(e),
+                }
+            }
+            Directive::Kvp(Kvp {
+                ident, value, op, ..
+            }) => {
+                match checker.visit_kvp(*op, &self.text[ident], ident, &self.text[value], value) {
+                    DiagResult::Ok | DiagResult::None => {}
+                    DiagResult::Err(e) => errs.extend(e),
                 }
-                Directive::Unknown(Unknown { span, prev_span }) => {
-                    match checker.visit_unknown(&self.text[span], span) {
-                        DiagResult::Ok | DiagResult::None => {}
-                        DiagResult::Err(e) => {
-                            errs.extend(e);
-                            if let Some(prev_span) = prev_span {
-                                let prev_line = &self.text[prev_span];
-                                if !prev_line.ends_with(r"\\") {
-                                    if let Some(beg) = prev_line.trim_end().rfind(r"\\") {
-                                        let err_sp = Span(prev_span.0 + beg, span.1);
-                                        errs.push(ReportedError {
-                                            span: err_sp,
-                                            kind: ErrorKind::SpaceAfterMultiline,
-                                        });
-                                    }
+            }
+            Directive::Unknown(Unknown { span, prev_span }) => {
+                match checker.visit_unknown(&self.text[span], span) {
+                    DiagResult::Ok | DiagResult::None => {}
+                    DiagResult::Err(e) => {
+                        errs.extend(e);
+                        if let Some(prev_span) = prev_span {
+                            let prev_line = &self.text[prev_span];
+                            if !prev_line.ends_with(r"\\") {
+                                if let Some(beg) = prev_line.trim_end().rfind(r"\\") {
+                                    // This line was meant to continue the previous one
+                                    let backslashes = prev_span.0 + beg;
+                                    errs.push(ReportedError {
+                                        span: *span,
+                                        kind: ErrorKind::SpaceAfterMultiline,
+                                        severity: Severity::Error,
+                                        related: Some(Span(backslashes, backslashes + 2)),
+                                    });
                                 }
                             }
                         }
                     }
                 }
             }
+            Directive::Blank(_) => {}
         }
 
-        errs
+        match checker.visit_directive(self, index) {
+            DiagResult::Ok | DiagResult::None => {}
+            DiagResult::Err(e) => errs.extend(e),
+        }
     }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct ReportedError {
     pub kind: ErrorKind,
     pub span: Span,
+    pub severity: Severity,
+    /// A second location that explains the error, like the `\\` that a stray
+    /// line was meant to continue.
+    pub related: Option<Span>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub enum ErrorKind {
     InvalidIdent,
     MalformedHeader,
     SpaceAfterMultiline,
     SlashSlashComent,
     BadValue,
-    Custom(String),
+    /// Any other error. `code` identifies the check that reported it, see
+    /// [`ErrorKind::code`].
+    Custom {
+        code: &'static str,
+        msg: String,
+    },
     Other,
 }
 
-#[derive(Clone, Debug)]
+impl ErrorKind {
+    /// A short, stable identifier for this kind of error.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ErrorKind::InvalidIdent => "invalid-ident",
+            ErrorKind::MalformedHeader => "malformed-header",
+            ErrorKind::SpaceAfterMultiline => "space-after-multiline",
+            ErrorKind::SlashSlashComent => "slash-comment",
+            ErrorKind::BadValue => "bad-value",
+            ErrorKind::Custom { code, .. } => code,
+            ErrorKind::Other => "other",
+        }
+    }
+
+    /// A human-readable description of this error.
+    pub fn message(&self) -> &str {
+        match self {
+            ErrorKind::InvalidIdent => "Invalid identifier",
+            ErrorKind::MalformedHeader => "Invalid header. The first character of a header line must be `[` and the last must be `]`.",
+            ErrorKind::SpaceAfterMultiline => "Space after backslashes ends the line continuation",
+            ErrorKind::SlashSlashComent => "UnrealScript-style comment (please use `;`)",
+            ErrorKind::BadValue => "Bad Value",
+            ErrorKind::Custom { msg, .. } => msg,
+            ErrorKind::Other => "Invalid config directive",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[must_use]
 pub enum DiagResult {
     /// The checked thing was found to match something expected.
@@ -184,28 +1211,69 @@ pub enum DiagResult {
     Err(Vec<ReportedError>),
 }
 
-pub fn try_report_comment(text: &str, span: &Span) -> DiagResult {
-    let trimmed_line = text.trim();
+impl DiagResult {
+    /// Combine the results of two checks of the same thing: errors from both
+    /// are kept, and if there are none, the result is `Ok` if either check
+    /// matched. `None` doesn't change the other result, so checks can be
+    /// chained like `a.merge(b).merge(c)`.
+    pub fn merge(self, other: DiagResult) -> DiagResult {
+        match (self, other) {
+            (DiagResult::Err(mut a), DiagResult::Err(b)) => {
+                a.extend(b);
+                DiagResult::Err(a)
+            }
+            (e @ DiagResult::Err(_), _) | (_, e @ DiagResult::Err(_)) => e,
+            (DiagResult::Ok, _) | (_, DiagResult::Ok) => DiagResult::Ok,
+            (DiagResult::None, DiagResult::None) => DiagResult::None,
+        }
+    }
+}
+
+/// Group `errors` by the 1-based line they start on.
+pub fn group_by_line(
+    errors: impl IntoIterator<Item = ReportedError>,
+    line_index: &LineIndex<'_>,
+) -> BTreeMap<u32, Vec<ReportedError>> {
+    let mut lines = BTreeMap::new();
+    for e in errors {
+        lines
+            .entry(line_index.line(e.span.0))
+            .or_insert_with(Vec::new)
+            .push(e);
+    }
+    lines
+}
 
+/// What a line that is neither a section header nor a KVP was likely meant to be.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineClass {
+    /// A `;` comment.
+    Comment,
+    /// A `//` comment, which UE3 doesn't support.
+    SlashComment,
+    /// Something in brackets that the parser didn't accept as a section header.
+    MalformedHeader,
+    /// An operation and a key without `=`, like a truncated `+MyArray`.
+    MissingEquals,
+    Other,
+}
+
+/// Guess what `line` was meant to be. These are the heuristics
+/// [`SimpleSyntaxValidator`] uses for [`Directive::Unknown`] lines, so that
+/// custom validators can reuse them.
+pub fn classify_line(line: &str) -> LineClass {
+    let trimmed_line = line.trim();
     if trimmed_line.starts_with(';') {
-        DiagResult::Ok
+        return LineClass::Comment;
     } else if trimmed_line.starts_with(r"//") {
-        DiagResult::Err(vec![ReportedError {
-            span: *span,
-            kind: ErrorKind::SlashSlashComent,
-        }])
-    } else {
-        DiagResult::None
+        return LineClass::SlashComment;
     }
-}
 
-pub fn try_report_section_error(line: &str, span: &Span) -> DiagResult {
     let trimmed_line = if let Some(pos) = line.find(';') {
         line[..pos].trim()
     } else {
-        line.trim()
+        trimmed_line
     };
-
     if matches!(
         (
             trimmed_line.as_bytes().first(),
@@ -213,12 +1281,38 @@ pub fn try_report_section_error(line: &str, span: &Span) -> DiagResult {
         ),
         (Some(b'['), Some(b']'))
     ) {
-        DiagResult::Err(vec![ReportedError {
+        LineClass::MalformedHeader
+    } else if trimmed_line.starts_with(['+', '.', '-', '!']) && KEY.is_match(&trimmed_line[1..]) {
+        LineClass::MissingEquals
+    } else {
+        LineClass::Other
+    }
+}
+
+pub fn try_report_comment(text: &str, span: &Span) -> DiagResult {
+    match classify_line(text) {
+        LineClass::Comment => DiagResult::Ok,
+        LineClass::SlashComment => DiagResult::Err(vec![ReportedError {
+            span: *span,
+            kind: ErrorKind::SlashSlashComent,
+            severity: Severity::Error,
+            related: None,
+        }]),
+        LineClass::MalformedHeader | LineClass::MissingEquals | LineClass::Other => {
+            DiagResult::None
+        }
+    }
+}
+
+pub fn try_report_section_error(line: &str, span: &Span) -> DiagResult {
+    match classify_line(line) {
+        LineClass::MalformedHeader => DiagResult::Err(vec![ReportedError {
             span: *span,
             kind: ErrorKind::MalformedHeader,
-        }])
-    } else {
-        DiagResult::None
+            severity: Severity::Error,
+            related: None,
+        }]),
+        _ => DiagResult::None,
     }
 }
 
@@ -237,54 +1331,54 @@ pub fn validate_property_text(text: &str, span: &Span) -> DiagResult {
     }
 
     // First, clear out the backslashes and direct newlines
-    let mut reduced = String::new();
-    let mut part_span = Span(0, text.len());
-
-    while let Some(b' ' | b'\t') = text[part_span].as_bytes().first() {
-        part_span.0 += 1;
-    }
-
-    while let Some(b' ' | b'\t') = text[part_span].as_bytes().last() {
-        part_span.1 -= 1;
-    }
-
-    loop {
-        match text[part_span].find(|c| matches!(c, '\r' | '\n')) {
-            Some(eol) => {
-                if text.get((part_span.0 + eol - 2)..(part_span.0 + eol)) == Some(r"\\") {
-                    reduced.push_str(&text[(part_span.0)..(part_span.0 + eol - 2)]);
-                    reduced.push_str("  ");
-                    part_span.0 += eol;
-
-                    while matches!(
-                        text[part_span].as_bytes().first(),
-                        Some(b'\t' | b'\r' | b'\n')
-                    ) {
-                        part_span.0 += 1;
-                        reduced.push(' ');
-                    }
-                } else {
-                    unreachable!(r"newline character without preceding \\ would have been split")
-                }
-            }
-            None => {
-                if text.get(text.len().saturating_sub(2)..text.len()) == Some(r"\\") {
-                    return DiagResult::Err(vec![ReportedError {
-                        kind: ErrorKind::Custom(r"Trailing \\ without following line".to_owned()),
-                        span: Span(span.0 + part_span.0, span.0 + part_span.1),
-                    }]);
-                } else {
-                    reduced.push_str(&text[part_span]);
-                    break;
-                }
-            }
-        }
+    let (reduced, last_line) = join_continuations(text);
+    if text.ends_with(r"\\") {
+        return DiagResult::Err(vec![ReportedError {
+            kind: ErrorKind::Custom {
+                code: "trailing-continuation",
+                msg: r"Trailing \\ without following line".to_owned(),
+            },
+            span: Span(span.0 + last_line.0, span.0 + last_line.1),
+            severity: Severity::Error,
+            related: None,
+        }]);
     }
+    // Continuations are replaced with the same number of spaces, so positions
+    // in `reduced` are offset from `text` by the leading whitespace only
+    let lead = text.len() - text.trim_start_matches([' ', '\t']).len();
 
     // Then, unescape if needed
     if reduced.as_bytes().first() == Some(&b'"') {
-        // TODO
-        DiagResult::None
+        // The string itself can contain anything, but nothing may follow it
+        let end = match tokenize_spanned(&reduced).next() {
+            Some((Token::Quoted(s), end)) if s.len() > 1 && s.ends_with('"') => end.1,
+            _ => {
+                return DiagResult::Err(vec![ReportedError {
+                    kind: ErrorKind::Custom {
+                        code: "unterminated-string",
+                        msg: "quoted value is missing its closing `\"`".to_owned(),
+                    },
+                    span: Span(span.0 + lead, span.1),
+                    severity: Severity::Error,
+                    related: None,
+                }])
+            }
+        };
+        let rest = &reduced[end..];
+        let trimmed = rest.trim_start();
+        if trimmed.is_empty() {
+            return DiagResult::Ok;
+        }
+        let start = span.0 + lead + end + (rest.len() - trimmed.len());
+        DiagResult::Err(vec![ReportedError {
+            kind: ErrorKind::Custom {
+                code: "text-after-quote",
+                msg: "unexpected text after quoted value".to_owned(),
+            },
+            span: Span(start, span.1),
+            severity: Severity::Warning,
+            related: None,
+        }])
     } else {
         if matches_bool(&reduced) {
             return DiagResult::Ok;
@@ -301,15 +1395,58 @@ pub fn validate_property_text(text: &str, span: &Span) -> DiagResult {
         let mut adj_span = *span;
 
         if reduced.as_bytes().first() == Some(&b'(') {
-            match struct_syntax::parse(&reduced) {
-                Ok(_) => {
-                    return DiagResult::Ok;
+            // A missing `)` at the end of a multiline value would otherwise be
+            // reported as an unexpected end, far away from the actual mistake
+            if let Some(pos) = unbalanced_paren(&reduced) {
+                return DiagResult::Err(vec![ReportedError {
+                    kind: ErrorKind::Custom {
+                        code: "unbalanced-parens",
+                        msg: "unbalanced parentheses in value".to_owned(),
+                    },
+                    span: Span(span.0 + lead + pos, span.1),
+                    severity: Severity::Error,
+                    related: None,
+                }]);
+            }
+            // Arrays of structs like `((A=1), (B=2))` are values too
+            match struct_syntax::parse_value(&reduced) {
+                Ok(value) => {
+                    let mut issues = vec![];
+                    struct_value_issues(&value, &mut issues);
+                    if issues.is_empty() {
+                        return DiagResult::Ok;
+                    }
+                    return DiagResult::Err(
+                        issues
+                            .into_iter()
+                            .map(|(text, code, msg, severity)| {
+                                // `text` borrows from `reduced`
+                                let start = span.0
+                                    + lead
+                                    + (text.as_ptr() as usize - reduced.as_ptr() as usize);
+                                ReportedError {
+                                    kind: ErrorKind::Custom {
+                                        code,
+                                        msg: msg.to_owned(),
+                                    },
+                                    span: Span(start, start + text.len()),
+                                    severity,
+                                    related: None,
+                                }
+                            })
+                            .collect(),
+                    );
                 }
                 Err(e) => {
-                    adj_span.0 += e.pos;
+                    adj_span.0 += lead + e.pos;
                     return DiagResult::Err(vec![ReportedError {
-                        kind: ErrorKind::Custom(e.msg),
+                        kind: ErrorKind::Custom {
+                            code: "struct-syntax",
+                            msg: e.msg,
+                        },
                         span: adj_span,
+                        severity: Severity::Error,
+                        related: None,
                     }]);
                 }
             }
@@ -318,10 +1455,86 @@ pub fn validate_property_text(text: &str, span: &Span) -> DiagResult {
         DiagResult::Err(vec![ReportedError {
             kind: ErrorKind::BadValue,
             span: adj_span,
+            severity: Severity::Error,
+            related: None,
         }])
     }
 }
 
+/// The position of the first `)` or `]` without a matching opening one, or of the
+/// last character if some are left open. Quoted strings are skipped.
+fn unbalanced_paren(text: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, b) in text.bytes().enumerate() {
+        match b {
+            _ if escaped => escaped = false,
+            b'\\' if quoted => escaped = true,
+            b'"' => quoted = !quoted,
+            _ if quoted => {}
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => match depth.checked_sub(1) {
+                Some(d) => depth = d,
+                None => return Some(i),
+            },
+            _ => {}
+        }
+    }
+    (depth > 0).then(|| text.char_indices().next_back().map_or(0, |(i, _)| i))
+}
+
+/// Collect the property names in `value` and all nested structs and arrays that
+/// aren't identifiers, and the terminals that look like numbers but don't parse.
+fn struct_value_issues<'t>(
+    value: &struct_syntax::PropValue<'t>,
+    issues: &mut Vec<(&'t str, &'static str, &'static str, Severity)>,
+) {
+    match value {
+        struct_syntax::PropValue::Struct(s) => {
+            for (name, value) in &s.children {
+                let trimmed = name.name.trim_end();
+                if !IDENT.is_match(trimmed) {
+                    issues.push((
+                        trimmed,
+                        "invalid-struct-name",
+                        "invalid struct property name",
+                        Severity::Error,
+                    ));
+                }
+                struct_value_issues(value, issues);
+            }
+        }
+        struct_syntax::PropValue::Array(a) => {
+            for v in &a.elems {
+                struct_value_issues(v, issues);
+            }
+        }
+        // Anything that parses is a `Number` already, so only names can be broken numbers
+        struct_syntax::PropValue::Name(s) if is_broken_number(s) => {
+            issues.push((
+                s.trim_end(),
+                "invalid-number",
+                "invalid number",
+                Severity::Warning,
+            ));
+        }
+        _ => {}
+    }
+}
+
+/// Whether `text` starts like a number, with a digit after an optional sign or `.`,
+/// but does not parse as one.
+/// UE3 accepts a trailing `f` on floats, so `1.5f` is not reported.
+fn is_broken_number(text: &str) -> bool {
+    let digits = text.trim_start_matches(['-', '+', '.']);
+    digits.starts_with(|c: char| c.is_ascii_digit()) && {
+        let text = text.trim_end();
+        let text = text.strip_suffix(['f', 'F']).unwrap_or(text);
+        text.parse::<f64>().is_err()
+    }
+}
+
 fn matches_bool(text: &str) -> bool {
     matches!(&*text.to_ascii_lowercase(), "true" | "false")
 }
@@ -334,12 +1547,46 @@ fn matches_ident(text: &str) -> bool {
     IDENT.is_match(text)
 }
 
+/// Find the first character that doesn't fit the `OBJECT` grammar, i.e. one or two
+/// identifiers separated by `.` or a space. Returns `None` if the text is only
+/// cut short, like `Package.`, since there's no single character to blame then.
+fn first_invalid_object_char(text: &str) -> Option<(usize, char)> {
+    let mut need_letter = true;
+    let mut seen_separator = false;
+    for (i, c) in text.char_indices() {
+        if need_letter {
+            if !c.is_ascii_alphabetic() {
+                return Some((i, c));
+            }
+            need_letter = false;
+        } else if matches!(c, '.' | ' ') && !seen_separator {
+            seen_separator = true;
+            need_letter = true;
+        } else if !(c.is_ascii_alphanumeric() || c == '_') {
+            return Some((i, c));
+        }
+    }
+    None
+}
+
 #[cfg(test)]
 mod tests {
+    use std::{cell::Cell, ops::ControlFlow};
+
     use expect_test::expect;
 
-    use super::{KEY, OBJECT};
-    use crate::{check::SimpleSyntaxValidator, parse::Directives};
+    use super::{
+        classify_line, validate_property_text, ArrayElementValidator, BareSectionValidator,
+        CompositeValidator, ControlCharValidator, DefaultPropertiesValidator, DiagResult,
+        DuplicateInsertValidator, DuplicateKeyValidator, DuplicateSectionValidator,
+        EmptyValueValidator, ErrorKind, FnValidator, LineClass, MaxLineLengthValidator,
+        ReplacementCharValidator, ReportedError, Severity, UnquotedWhitespaceValidator,
+        ValidationContext, Validator, WhitespaceValidator, KEY, OBJECT,
+    };
+    use crate::{
+        check::SimpleSyntaxValidator,
+        parse::{Directives, LineIndex, Span},
+    };
 
     #[test]
     fn regex_key() {
@@ -378,13 +1625,21 @@ mod tests {
             Directives {
                 text: "[MyPackage.MyClass] ",
                 directives: [
-                    Unknown(
-                        Unknown {
+                    SectionHeader(
+                        SectionHeader {
                             span: Span(
                                 0,
                                 20,
                             ),
-                            prev_span: None,
+                            trimmed: Span(
+                                0,
+                                19,
+                            ),
+                            obj_name: Span(
+                                1,
+                                18,
+                            ),
+                            comment: None,
                         },
                     ),
                 ],
@@ -401,10 +1656,19 @@ mod tests {
                         0,
                         20,
                     ),
+                    severity: Error,
+                    related: None,
                 },
             ]
         "#]];
         expected_errs.assert_debug_eq(&dirs.validate(&SimpleSyntaxValidator));
+        assert_eq!(
+            dirs.validate(&SimpleSyntaxValidator)[0].kind,
+            ErrorKind::MalformedHeader
+        );
+        // Only the syntax validator reports the whitespace
+        assert!(dirs.validate(&FnValidator::new()).is_empty());
+        assert!(dirs.validate(&WhitespaceValidator).is_empty());
     }
 
     #[test]
@@ -431,6 +1695,8 @@ mod tests {
                                 31,
                             ),
                             op: InsertUnique,
+                            comment: None,
+                            line_count: 1,
                         },
                     ),
                     Unknown(
@@ -456,31 +1722,134 @@ mod tests {
         let expected_errs = expect![[r#"
             [
                 ReportedError {
-                    kind: Custom(
-                        "Expected `=`",
+                    kind: Custom {
+                        code: "unbalanced-parens",
+                        msg: "unbalanced parentheses in value",
+                    },
+                    span: Span(
+                        29,
+                        31,
+                    ),
+                    severity: Error,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Other,
+                    span: Span(
+                        32,
+                        37,
+                    ),
+                    severity: Error,
+                    related: None,
+                },
+                ReportedError {
+                    kind: SpaceAfterMultiline,
+                    span: Span(
+                        32,
+                        37,
+                    ),
+                    severity: Error,
+                    related: Some(
+                        Span(
+                            28,
+                            30,
+                        ),
+                    ),
+                },
+            ]
+        "#]];
+        expected_errs.assert_debug_eq(&dirs.validate(&SimpleSyntaxValidator))
+    }
+
+    #[test]
+    fn space_after_multiline_related() {
+        let text = "[A.B]\n+Arr=(A=1, \\\\  \n  B)";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        let errs = errs
+            .iter()
+            .filter(|e| e.kind == ErrorKind::SpaceAfterMultiline)
+            .collect::<Vec<_>>();
+        // Reported once, with the line that was meant to be continued
+        assert_eq!(errs.len(), 1);
+        let e = errs[0];
+        assert_eq!(&text[e.span], "  B)");
+        assert_eq!(&text[e.related.unwrap()], r"\\");
+    }
+
+    #[test]
+    fn space_after_multiline_kvp() {
+        let text = "+Arr=(A=1, \\\\ \nKey=value\n+Arr=(A=1, \\\\\t\n\n[A.B]\n+Arr=(A=1, \\\\ \n; comment\n+Arr=(A=1, \\\\ ";
+        let expected_errs = expect![[r#"
+            [
+                ReportedError {
+                    kind: SpaceAfterMultiline,
+                    span: Span(
+                        11,
+                        14,
+                    ),
+                    severity: Error,
+                    related: Some(
+                        Span(
+                            11,
+                            13,
+                        ),
                     ),
+                },
+                ReportedError {
+                    kind: SpaceAfterMultiline,
                     span: Span(
-                        30,
-                        31,
+                        36,
+                        39,
+                    ),
+                    severity: Error,
+                    related: Some(
+                        Span(
+                            36,
+                            38,
+                        ),
                     ),
                 },
                 ReportedError {
-                    kind: Other,
+                    kind: SpaceAfterMultiline,
                     span: Span(
-                        32,
-                        37,
+                        58,
+                        61,
+                    ),
+                    severity: Error,
+                    related: Some(
+                        Span(
+                            58,
+                            60,
+                        ),
                     ),
                 },
                 ReportedError {
                     kind: SpaceAfterMultiline,
                     span: Span(
-                        28,
-                        37,
+                        83,
+                        86,
+                    ),
+                    severity: Error,
+                    related: Some(
+                        Span(
+                            83,
+                            85,
+                        ),
                     ),
                 },
             ]
         "#]];
-        expected_errs.assert_debug_eq(&dirs.validate(&SimpleSyntaxValidator))
+        let errs = Directives::from_text(text)
+            .validate(&SimpleSyntaxValidator)
+            .into_iter()
+            .filter(|e| e.kind == ErrorKind::SpaceAfterMultiline)
+            .collect::<Vec<_>>();
+        expected_errs.assert_debug_eq(&errs);
+
+        // Only the syntax validator reports these
+        let directives = Directives::from_text(text);
+        assert!(directives.validate(&FnValidator::new()).is_empty());
+        assert!(directives.validate(&WhitespaceValidator).is_empty());
     }
 
     #[test]
@@ -496,10 +1865,15 @@ mod tests {
                                 0,
                                 19,
                             ),
+                            trimmed: Span(
+                                0,
+                                19,
+                            ),
                             obj_name: Span(
                                 1,
                                 18,
                             ),
+                            comment: None,
                         },
                     ),
                 ],
@@ -530,6 +1904,8 @@ mod tests {
                                 18,
                             ),
                             op: InsertUnique,
+                            comment: None,
+                            line_count: 1,
                         },
                     ),
                 ],
@@ -546,6 +1922,8 @@ mod tests {
                         1,
                         16,
                     ),
+                    severity: Error,
+                    related: None,
                 },
             ]
         "#]];
@@ -578,6 +1956,8 @@ mod tests {
                                 149,
                             ),
                             op: InsertUnique,
+                            comment: None,
+                            line_count: 5,
                         },
                     ),
                 ],
@@ -592,5 +1972,850 @@ mod tests {
         expected_errs.assert_debug_eq(&dirs.validate(&SimpleSyntaxValidator));
     }
 
+    #[test]
+    fn fn_validator() {
+        let text = "[Engine.Cheats]\nCheat=1\n+Cheats=2\nCheat[1]=3\nCheat=4";
+        let validator = FnValidator::new().kvp(|_op, prop, prop_span, _text, _text_span| {
+            if prop == "Cheat" {
+                DiagResult::Err(vec![ReportedError {
+                    kind: ErrorKind::Custom {
+                        code: "no-cheating",
+                        msg: "No cheating".to_owned(),
+                    },
+                    span: *prop_span,
+                    severity: Severity::Error,
+                    related: None,
+                }])
+            } else {
+                DiagResult::Ok
+            }
+        });
+
+        let expected_errs = expect![[r#"
+            [
+                ReportedError {
+                    kind: Custom {
+                        code: "no-cheating",
+                        msg: "No cheating",
+                    },
+                    span: Span(
+                        16,
+                        21,
+                    ),
+                    severity: Error,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Custom {
+                        code: "no-cheating",
+                        msg: "No cheating",
+                    },
+                    span: Span(
+                        45,
+                        50,
+                    ),
+                    severity: Error,
+                    related: None,
+                },
+            ]
+        "#]];
+        expected_errs.assert_debug_eq(&Directives::from_text(text).validate(&validator));
+    }
+
+    #[test]
+    fn mixed_indentation() {
+        let text = "[A.B]\n\t  ; Comment\n+Arr=(A=1, \\\\\n \tB=2)\n\t\tOk=1\n \t Key=1";
+        let validator = CompositeValidator::new()
+            .with(SimpleSyntaxValidator)
+            .with(WhitespaceValidator);
+
+        let expected_errs = expect![[r#"
+            [
+                ReportedError {
+                    kind: Custom {
+                        code: "mixed-indentation",
+                        msg: "mixed tabs and spaces",
+                    },
+                    span: Span(
+                        6,
+                        9,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Custom {
+                        code: "mixed-indentation",
+                        msg: "mixed tabs and spaces",
+                    },
+                    span: Span(
+                        33,
+                        35,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Custom {
+                        code: "mixed-indentation",
+                        msg: "mixed tabs and spaces",
+                    },
+                    span: Span(
+                        47,
+                        50,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+            ]
+        "#]];
+        expected_errs.assert_debug_eq(&Directives::from_text(text).validate(&validator));
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        let text = "[A.B]\nKey=1\n+Arr=1\n+Arr=1\nKey=2\n[A.C]\nKey=3\n[a.b]\nkey=4";
+        let expected_errs = expect![[r#"
+            [
+                ReportedError {
+                    kind: Custom {
+                        code: "duplicate-key",
+                        msg: "duplicate key `Key`",
+                    },
+                    span: Span(
+                        26,
+                        29,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Custom {
+                        code: "duplicate-key",
+                        msg: "key `key` differs only by case from `Key`",
+                    },
+                    span: Span(
+                        50,
+                        53,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+            ]
+        "#]];
+        let validator = DuplicateKeyValidator::new();
+        expected_errs.assert_debug_eq(&Directives::from_text(text).validate(&validator));
+
+        // Nothing carries over from the previous file
+        assert!(Directives::from_text("Key=1\n[a.c]\nKey=1")
+            .validate(&validator)
+            .is_empty());
+    }
+
+    #[test]
+    fn bare_sections() {
+        let text = "[GameEngine]\nKey=1\n[Engine.GameEngine]\nKey=2\n[Bad-Name]";
+        let expected_errs = expect![[r#"
+            [
+                ReportedError {
+                    kind: Custom {
+                        code: "bare-section",
+                        msg: "section header has no package.class form",
+                    },
+                    span: Span(
+                        1,
+                        11,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+            ]
+        "#]];
+        expected_errs.assert_debug_eq(&Directives::from_text(text).validate(&BareSectionValidator));
+        assert_eq!(
+            Directives::from_text(text)
+                .validate(&SimpleSyntaxValidator)
+                .iter()
+                .filter(|e| e.severity == Severity::Warning)
+                .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn empty_values() {
+        let text = "[A.B]\nName=\nTitle=  \n!Array=\n-Array=\nKey=1";
+        let errs = Directives::from_text(text).validate(&EmptyValueValidator);
+        let spans = errs.iter().map(|e| &text[e.span]).collect::<Vec<_>>();
+        assert_eq!(spans, ["Name", "Title"]);
+        assert!(errs.iter().all(|e| e.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn array_elements() {
+        let text =
+            "[A.B]\n+Items=(A,B)\n+Items=(Name=A)\n.Items=(A)\nItems=(A,B)\n.Items=((A=1),(A=2))";
+        let errs = Directives::from_text(text).validate(&ArrayElementValidator);
+        let spans = errs.iter().map(|e| &text[e.span]).collect::<Vec<_>>();
+        assert_eq!(spans, ["(A,B)", "((A=1),(A=2))"]);
+        assert!(errs.iter().all(|e| e.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn unquoted_whitespace() {
+        let text = "[A.B]\nA=(Name=Hello World)\nB=(Name=\"Hello World\")\nC=Hello World\nD=(X=(Hello\tWorld ,Fine ), Y=1 )";
+        let errs = Directives::from_text(text).validate(&UnquotedWhitespaceValidator);
+        let spans = errs.iter().map(|e| &text[e.span]).collect::<Vec<_>>();
+        assert_eq!(spans, ["Hello World", "Hello\tWorld"]);
+        assert!(errs.iter().all(|e| e.severity == Severity::Warning));
+    }
+
+    #[test]
+    fn control_characters() {
+        let text = "[A.B]\nKey=Be\x07ll\n+Arr=(A=\"x\ty\", \\\\\r\n  B=\u{9b}1)\nOk=Fine";
+        let errs = Directives::from_text(text).validate(&ControlCharValidator);
+        let spans = errs.iter().map(|e| e.span).collect::<Vec<_>>();
+        let bell = text.find('\x07').unwrap();
+        let csi = text.find('\u{9b}').unwrap();
+        assert_eq!(spans, [Span(bell, bell + 1), Span(csi, csi + 2)]);
+        assert!(errs.iter().all(|e| e.severity == Severity::Error
+            && e.kind
+                == ErrorKind::Custom {
+                    code: "control-character",
+                    msg: "control character in value".to_owned(),
+                }));
+    }
+
+    #[test]
+    fn max_line_length() {
+        let text = "[Engine.SomeLongClassName]
+[A.B]  ; a long comment
+Short=1
+Exactly20Characters=
+    Indented=123456789
++Arr=(A=1, B=2, C=3, D=4)
++Arr=(A=1, \\\\
+      B=2, C=3, D=4, E=5, \\\\
+      F=6)
+; a comment that is far too long
+";
+        let validator = MaxLineLengthValidator { limit: 20 };
+        let errs = Directives::from_text(text).validate(&validator);
+        let expected = expect![[r#"
+            [
+                "[Engine.SomeLongClassName]",
+                "[A.B]  ; a long comment",
+                "    Indented=123456789",
+                "+Arr=(A=1, B=2, C=3, D=4)",
+                "      B=2, C=3, D=4, E=5, \\\\",
+                "; a comment that is far too long",
+            ]
+        "#]];
+        expected.assert_debug_eq(&errs.iter().map(|e| &text[e.span]).collect::<Vec<_>>());
+        assert!(errs.iter().all(|e| e.severity == Severity::Warning
+            && e.kind
+                == ErrorKind::Custom {
+                    code: "line-too-long",
+                    msg: "line exceeds 20 characters".to_owned(),
+                }));
+    }
+
+    #[test]
+    fn replacement_characters() {
+        // Latin-1 `é` and a UTF-16 byte order mark aren't valid UTF-8
+        let bytes = b"\xff\xfe[A.B]\nName=Caf\xe9\n; ok\nK\xe9y=1";
+        let owned = Directives::from_bytes_lossy(bytes);
+        let directives = owned.as_directives();
+        let errs = directives.validate(&ReplacementCharValidator);
+        let lines = errs
+            .iter()
+            .map(|e| {
+                assert_eq!(&directives.text[e.span], "\u{FFFD}");
+                directives.text[..e.span.0].matches('\n').count() + 1
+            })
+            .collect::<Vec<_>>();
+        // The byte order mark is two replacement characters, and makes the header an unknown line
+        assert_eq!(lines, [1, 1, 2, 4]);
+        assert!(errs.iter().all(|e| e.kind
+            == ErrorKind::Custom {
+                code: "replacement-character",
+                msg: "possible encoding error (replacement character)".to_owned(),
+            }));
+
+        let text = "[A.B]\nName=Caf\u{e9}";
+        assert!(Directives::from_text(text)
+            .validate(&ReplacementCharValidator)
+            .is_empty());
+    }
+
+    #[test]
+    fn merge() {
+        let e = |start| ReportedError {
+            kind: ErrorKind::Other,
+            span: Span(start, start + 1),
+            severity: Severity::Error,
+            related: None,
+        };
+        let err = |start| DiagResult::Err(vec![e(start)]);
+        let none = || DiagResult::None;
+        let ok = || DiagResult::Ok;
+
+        assert_eq!(none().merge(none()), DiagResult::None);
+        assert_eq!(none().merge(ok()), DiagResult::Ok);
+        assert_eq!(ok().merge(none()), DiagResult::Ok);
+        assert_eq!(ok().merge(ok()), DiagResult::Ok);
+        assert_eq!(none().merge(err(0)), err(0));
+        assert_eq!(err(0).merge(none()), err(0));
+        assert_eq!(ok().merge(err(0)), err(0));
+        assert_eq!(err(0).merge(ok()), err(0));
+        assert_eq!(err(0).merge(err(1)), DiagResult::Err(vec![e(0), e(1)]));
+    }
+
+    #[test]
+    fn invalid_section_chars() {
+        let text = "[My-Package.Class]\n[Pkg.Cls.More]\n[1Pkg]\n[Pkg.]\n[Pkg.Clsé]\n[Pkg Cls]";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        let found = errs
+            .iter()
+            .map(|e| (&text[e.span], e.kind.message()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            found,
+            [
+                ("-", "unexpected character `-` in section name"),
+                (".", "unexpected character `.` in section name"),
+                ("1", "unexpected character `1` in section name"),
+                ("Pkg.", "Invalid identifier"),
+                ("é", "unexpected character `é` in section name"),
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_section_name() {
+        let text = "[]\nKey=1\n[] ; comment\n[]";
+        let directives = Directives::from_text(text);
+        let errs = directives.validate(&SimpleSyntaxValidator);
+        assert_eq!(errs.len(), 3);
+        assert!(errs.iter().all(|e| e.kind.code() == "empty-section-name"
+            && e.kind.message() == "empty section name"
+            && &text[e.span] == "[]"));
+
+        // Everything else copes with the empty name as well
+        assert_eq!(directives.evaluate()[0].name, "");
+        assert_eq!(directives.stats().sections, 3);
+        directives.format(Default::default());
+
+        // Called directly, the name doesn't have to follow a `[`
+        match SimpleSyntaxValidator.visit_section_header("", &Span(0, 0)) {
+            DiagResult::Err(e) => assert_eq!(e[0].span, Span(0, 1)),
+            _ => panic!("empty name accepted"),
+        }
+        directives.canonicalize();
+    }
+
+    #[test]
+    fn duplicate_sections() {
+        let text = "[Engine.GameEngine]\nKey=1\n[Engine.Other]\n[engine.gameengine]\nKey=2";
+        let errs = Directives::from_text(text).validate(&DuplicateSectionValidator::new());
+        assert_eq!(errs.len(), 1);
+        assert_eq!(&text[errs[0].span], "engine.gameengine");
+        assert_eq!(errs[0].severity, Severity::Warning);
+        assert_eq!(
+            errs[0].kind,
+            ErrorKind::Custom {
+                code: "duplicate-section",
+                msg: "duplicate section header".to_owned(),
+            }
+        );
+
+        // Each validation starts over, in a context as well
+        let validator = DuplicateSectionValidator::new();
+        let clean = Directives::from_text("[Engine.GameEngine]\nKey=1");
+        assert_eq!(Directives::from_text(text).validate(&validator).len(), 1);
+        assert!(clean.validate(&validator).is_empty());
+        let composite = CompositeValidator::new().with(DuplicateSectionValidator::new());
+        let mut context = ValidationContext::new(&composite);
+        assert!(context.validate_into(&clean).is_empty());
+        assert!(context.validate_into(&clean).is_empty());
+    }
+
+    #[test]
+    fn pasted_defaultproperties() {
+        let text = "Top=1
+[XComGame.X2Item]
+\tBegin Object Class=StaticMeshComponent Name=Mesh0
+\t\tStaticMesh=Foo
+\tEnd Object
+\tComponents.Add(Mesh0)
+\tHealth=100;
+defaultproperties
+{
+}
+Arr[0]=(A=1; B=2)
+";
+        let directives = Directives::from_text(text);
+        let plain = directives.validate(&SimpleSyntaxValidator);
+        let end_object = text.find("\tEnd Object").unwrap();
+        assert!(plain
+            .iter()
+            .any(|e| e.span.0 == end_object && e.kind == ErrorKind::Other));
+
+        let validator = DefaultPropertiesValidator::new();
+        let errs = directives.validate(&validator);
+        let expected = expect![[r#"
+            Top=1: property before the first section header, config properties belong in a `[Package.Class]` section
+            Begin Object Class=StaticMeshComponent Name=Mesh0: `Begin Object`/`End Object` blocks only work in defaultproperties, not in config files
+            End Object: `Begin Object`/`End Object` blocks only work in defaultproperties, not in config files
+            Components.Add(Mesh0): array functions don't work in config files, use `+Key=Value` or `-Key=Value` instead
+            100;: config values don't end with `;`, remove it if this was copied from defaultproperties
+            defaultproperties: looks like a defaultproperties block, config files use `[Package.Class]` sections instead
+            {: looks like a defaultproperties block, config files use `[Package.Class]` sections instead
+            }: looks like a defaultproperties block, config files use `[Package.Class]` sections instead
+        "#]];
+        let summary = errs
+            .iter()
+            .map(|e| match &e.kind {
+                ErrorKind::Custom { msg, .. } => format!("{}: {}\n", text[e.span].trim(), msg),
+                other => panic!("unexpected {:?}", other),
+            })
+            .collect::<String>();
+        expected.assert_eq(&summary);
+        assert!(errs.iter().all(|e| e.severity == Severity::Warning));
+
+        // The section header of the previous file doesn't count
+        let errs = Directives::from_text("Top=1\n[XComGame.X2Item]").validate(&validator);
+        assert_eq!(errs.len(), 1);
+    }
+
+    #[test]
+    fn duplicate_inserts() {
+        let text = "[A.B]\n+Achievements=X\n.Log=X\n.Log=X\n+achievements= X \n+Achievements=\"X\"\n+Achievements=Y\n[A.C]\n+Achievements=X\n[a.b]\n+Achievements=Y";
+        let validator = DuplicateInsertValidator::new();
+        let errs = Directives::from_text(text).validate(&validator);
+        let lines = errs
+            .iter()
+            .map(|e| text[..e.span.0].lines().count())
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [5, 6, 11]);
+        assert!(errs.iter().all(|e| e.severity == Severity::Warning
+            && e.kind
+                == ErrorKind::Custom {
+                    code: "duplicate-insert",
+                    msg: "duplicate unique insert".to_owned(),
+                }));
+
+        // Nothing carries over from the previous file
+        assert!(Directives::from_text("[A.B]\n+Achievements=X")
+            .validate(&validator)
+            .is_empty());
+    }
+
+    #[test]
+    fn case_conflicts() {
+        let text = "[A.B]\nGameName=X\ngamename=Y\nGameName=Z";
+        let expected_errs = expect![[r#"
+            [
+                ReportedError {
+                    kind: Custom {
+                        code: "duplicate-key",
+                        msg: "key `gamename` differs only by case from `GameName`",
+                    },
+                    span: Span(
+                        17,
+                        25,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Custom {
+                        code: "duplicate-key",
+                        msg: "duplicate key `GameName`",
+                    },
+                    span: Span(
+                        28,
+                        36,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+            ]
+        "#]];
+        expected_errs
+            .assert_debug_eq(&Directives::from_text(text).validate(&DuplicateKeyValidator::new()));
+    }
+
+    #[test]
+    fn double_operation() {
+        let text = "++Key=x\n+-Key=x\n+My-Array=x";
+        let expected_errs = expect![[r#"
+            [
+                ReportedError {
+                    kind: Custom {
+                        code: "extra-operation",
+                        msg: "unexpected operation character in key",
+                    },
+                    span: Span(
+                        1,
+                        5,
+                    ),
+                    severity: Error,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Custom {
+                        code: "extra-operation",
+                        msg: "unexpected operation character in key",
+                    },
+                    span: Span(
+                        9,
+                        13,
+                    ),
+                    severity: Error,
+                    related: None,
+                },
+                ReportedError {
+                    kind: InvalidIdent,
+                    span: Span(
+                        17,
+                        25,
+                    ),
+                    severity: Error,
+                    related: None,
+                },
+            ]
+        "#]];
+        expected_errs
+            .assert_debug_eq(&Directives::from_text(text).validate(&SimpleSyntaxValidator));
+    }
+
+    #[test]
+    fn group_by_line() {
+        let text = "[A.B]\n// Comment\nKey=1\n; Comment\n[A-B]\nKey=2";
+        let mut errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        // Errors spanning multiple lines are grouped by their first line
+        errs.push(ReportedError {
+            kind: ErrorKind::Other,
+            span: Span(14, 40),
+            severity: Severity::Warning,
+            related: None,
+        });
+        let grouped = super::group_by_line(errs, &LineIndex::new(text));
+        let lines = grouped
+            .iter()
+            .map(|(line, errs)| (*line, errs.iter().map(|e| e.kind.code()).collect()))
+            .collect::<Vec<(u32, Vec<_>)>>();
+        assert_eq!(
+            lines,
+            [
+                (2, vec!["slash-comment", "other"]),
+                (5, vec!["invalid-section-char"])
+            ]
+        );
+    }
+
+    #[test]
+    fn validate_directive() {
+        let text = "[A.B] \nKey=(A=)\nKey=(A=1, \\\\ \n  B)\n// Comment\n[A-B]";
+        let directives = Directives::from_text(text);
+        let mut errs = vec![];
+        for i in 0..directives.directives.len() {
+            errs.extend(directives.validate_directive(i, &SimpleSyntaxValidator));
+        }
+        assert_eq!(errs.len(), 7);
+        assert!(errs
+            .iter()
+            .any(|e| e.kind == ErrorKind::SpaceAfterMultiline));
+        assert_eq!(errs, directives.validate(&SimpleSyntaxValidator));
+    }
+
+    #[test]
+    fn validate_limited() {
+        let text = "// Comment\n".repeat(100);
+        let directives = Directives::from_text(&text);
+        assert_eq!(directives.validate(&SimpleSyntaxValidator).len(), 100);
+        let errs = directives.validate_limited(&SimpleSyntaxValidator, 5);
+        assert_eq!(errs.len(), 5);
+        assert_eq!(errs[4].span, Span(44, 54));
+    }
+
+    #[test]
+    fn validate_with_sink() {
+        let text = "// Comment\n".repeat(100);
+        let directives = Directives::from_text(&text);
+        let visited = Cell::new(0);
+        let validator = FnValidator::new().unknown(|text, span| {
+            visited.set(visited.get() + 1);
+            SimpleSyntaxValidator.visit_unknown(text, span)
+        });
+
+        let mut errs = vec![];
+        directives.validate_with_sink(&validator, |e| {
+            errs.push(e);
+            ControlFlow::Break(())
+        });
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].span, Span(0, 10));
+        assert_eq!(visited.get(), 1);
+
+        let mut count = 0;
+        directives.validate_with_sink(&validator, |_| {
+            count += 1;
+            ControlFlow::Continue(())
+        });
+        assert_eq!(count, 100);
+        assert_eq!(visited.get(), 101);
+    }
+
+    #[test]
+    fn validation_context() {
+        let first = Directives::from_text("[A.B]\n// bad\nKey=1\n(junk");
+        let second = Directives::from_text("[A.B]\nKey=(A=1");
+        let mut context = ValidationContext::new(&SimpleSyntaxValidator);
+
+        assert_eq!(
+            context.validate_into(&first),
+            &first.validate(&SimpleSyntaxValidator)[..]
+        );
+        assert_eq!(context.errors().len(), 2);
+        let capacity = context.errs.capacity();
+
+        assert_eq!(
+            context.validate_into(&second),
+            &second.validate(&SimpleSyntaxValidator)[..]
+        );
+        assert_eq!(context.errors().len(), 1);
+        assert_eq!(context.errs.capacity(), capacity);
+
+        assert!(context
+            .validate_into(&Directives::from_text("[A.B]\nKey=1"))
+            .is_empty());
+    }
+
+    #[test]
+    fn quoted_values() {
+        let text =
+            "[A.B]\nName=\"abc\"def\nName=\"abc\"\nName= \"a\\\"b\"  \nName=\"a\nb\"\nName=\"open";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        assert_eq!(errs.len(), 2);
+        assert_eq!(&text[errs[0].span], "def");
+        assert_eq!(errs[0].severity, Severity::Warning);
+        assert_eq!(
+            errs[0].kind,
+            ErrorKind::Custom {
+                code: "text-after-quote",
+                msg: "unexpected text after quoted value".to_owned(),
+            }
+        );
+        assert_eq!(&text[errs[1].span], "\"open");
+        assert_eq!(errs[1].severity, Severity::Error);
+        assert_eq!(errs[1].kind.code(), "unterminated-string");
+
+        let text = "\"abc\"";
+        assert_eq!(
+            validate_property_text(text, &Span(0, text.len())),
+            DiagResult::Ok
+        );
+    }
+
+    #[test]
+    fn error_spans_with_multibyte_chars() {
+        // Unclosed parentheses are reported at the last character, and struct
+        // errors after leading whitespace used to be off by its length
+        let text = "[A.B]\nKey=(€\nKey= (é]\nKey=\t(A=1, €)";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        let found = errs.iter().map(|e| &text[e.span]).collect::<Vec<_>>();
+        assert_eq!(found, ["€", "]", ")"]);
+    }
+
+    #[test]
+    fn continuation_reduction() {
+        // Continuations are replaced with spaces of the same length, so a single-line
+        // value (which doesn't need any reduction) must produce the same result as the
+        // equivalent value split over multiple lines.
+        let cases = [
+            ("(A=1,    B=2)", "(A=1, \\\\\nB=2)"),
+            ("(A=1,    B)", "(A=1, \\\\\nB)"),
+            ("  Name\t", "  Name\t"),
+        ];
+        for (single, multi) in &cases {
+            assert_eq!(
+                validate_property_text(single, &Span(0, single.len())),
+                validate_property_text(multi, &Span(0, multi.len())),
+            );
+        }
+
+        let expected = expect![[r#"
+            Err(
+                [
+                    ReportedError {
+                        kind: Custom {
+                            code: "struct-syntax",
+                            msg: "Expected `=`",
+                        },
+                        span: Span(
+                            10,
+                            11,
+                        ),
+                        severity: Error,
+                        related: None,
+                    },
+                ],
+            )
+        "#]];
+        expected.assert_debug_eq(&validate_property_text(
+            cases[1].1,
+            &Span(0, cases[1].1.len()),
+        ));
+    }
+
+    #[test]
+    fn classify_lines() {
+        let classes = [
+            "; ok",
+            "// bad",
+            "[broken ]",
+            "  [Engine.Engine] ; x",
+            "random text",
+            "+MyArray",
+            "-Arr[1] ; x",
+            "+",
+        ]
+        .iter()
+        .map(|l| classify_line(l))
+        .collect::<Vec<_>>();
+        assert_eq!(
+            classes,
+            [
+                LineClass::Comment,
+                LineClass::SlashComment,
+                LineClass::MalformedHeader,
+                LineClass::MalformedHeader,
+                LineClass::Other,
+                LineClass::MissingEquals,
+                LineClass::MissingEquals,
+                LineClass::Other,
+            ]
+        );
+    }
+
+    #[test]
+    fn missing_equals() {
+        let text = "[A.B]\n+MyArray\nMyArray\n";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        let kinds = errs
+            .iter()
+            .map(|e| (&text[e.span], &e.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                (
+                    "+MyArray",
+                    &ErrorKind::Custom {
+                        code: "missing-equals",
+                        msg: "operation without '='".to_owned(),
+                    }
+                ),
+                ("MyArray", &ErrorKind::Other),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiline_strings() {
+        let text = "[A.B]\nText=\"First line\n\nsecond line\"\nNext=(A=\"x\ny\", \\\\\n  B=\"z\")";
+        assert_eq!(
+            Directives::from_text(text).validate(&SimpleSyntaxValidator),
+            []
+        );
+    }
+
+    #[test]
+    fn stray_closing_paren() {
+        let text = "[A.B]\nKey=(A=1))\nNested=(A=(B=1, C=(1,2))))";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        let starts = errs.iter().map(|e| e.span.0).collect::<Vec<_>>();
+        assert_eq!(
+            starts,
+            [text.find("))").unwrap() + 1, text.rfind(')').unwrap()]
+        );
+        assert!(errs.iter().all(|e| e.kind
+            == ErrorKind::Custom {
+                code: "unbalanced-parens",
+                msg: "unbalanced parentheses in value".to_owned(),
+            }));
+    }
+
+    #[test]
+    fn unclosed_paren() {
+        let text =
+            "[A.B]\nArr=((A=1), \\\\\n  (A=2), \\\\\n  (A=3, B=\"(\")\nNext=(A=\")\", B=1)\nKey=1";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        assert_eq!(errs.len(), 1);
+        assert_eq!(
+            errs[0].kind,
+            ErrorKind::Custom {
+                code: "unbalanced-parens",
+                msg: "unbalanced parentheses in value".to_owned(),
+            }
+        );
+        let end = text.find("\nNext").unwrap();
+        assert_eq!(errs[0].span, Span(end - 1, end));
+    }
+
+    #[test]
+    fn struct_property_names() {
+        let text =
+            "[A.B]\nKey=(3Foo=1)\nNested= (A=(Foo-Bar=1), \\\\\n  B=((C=1),(_D=2)), Ok_1 =X)";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        let spans = errs.iter().map(|e| &text[e.span]).collect::<Vec<_>>();
+        assert_eq!(spans, ["3Foo", "Foo-Bar", "_D"]);
+        assert!(errs.iter().all(|e| e.kind
+            == ErrorKind::Custom {
+                code: "invalid-struct-name",
+                msg: "invalid struct property name".to_owned(),
+            }));
+    }
 
+    #[test]
+    fn struct_array_elements() {
+        let text = "[A.B]\nArr=((A=1), \\\\\n  (B=(C=2, D.E=3)), (F=1.5f, G=-0.5))\n\
+                    Nums=(1, 2.0.1, (H=12x))\nEmpty=()";
+        let errs = Directives::from_text(text).validate(&SimpleSyntaxValidator);
+        let found = errs
+            .iter()
+            .map(|e| (&text[e.span], &e.kind, e.severity))
+            .collect::<Vec<_>>();
+        expect![[r#"
+            [
+                (
+                    "D.E",
+                    Custom {
+                        code: "invalid-struct-name",
+                        msg: "invalid struct property name",
+                    },
+                    Error,
+                ),
+                (
+                    "2.0.1",
+                    Custom {
+                        code: "invalid-number",
+                        msg: "invalid number",
+                    },
+                    Warning,
+                ),
+                (
+                    "12x",
+                    Custom {
+                        code: "invalid-number",
+                        msg: "invalid number",
+                    },
+                    Warning,
+                ),
+            ]
+        "#]]
+        .assert_debug_eq(&found);
+    }
 }
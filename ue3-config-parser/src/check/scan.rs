@@ -0,0 +1,65 @@
+//! Hand-written equivalents of the `KEY`, `OBJECT`, and `IDENT` regexes,
+//! used instead of the `regex` crate when the default `regex` feature is disabled.
+
+pub(super) struct Matcher(fn(&str) -> bool);
+
+impl Matcher {
+    pub(super) fn is_match(&self, text: &str) -> bool {
+        (self.0)(text)
+    }
+}
+
+/// `^[A-Za-z][A-Za-z0-9_]*(\[(0|[1-9][0-9]*)\]|\((0|[1-9][0-9]*)\))?$`
+pub(super) static KEY: Matcher = Matcher(is_key);
+
+/// `^[A-Za-z][A-Za-z0-9_]*([ \.][A-Za-z][A-Za-z0-9_]*)?$`
+pub(super) static OBJECT: Matcher = Matcher(is_object);
+
+/// `^[A-Za-z][A-Za-z0-9_]*$`
+pub(super) static IDENT: Matcher = Matcher(is_ident);
+
+/// Strip a leading `[A-Za-z][A-Za-z0-9_]*` from `text`.
+fn strip_ident(text: &str) -> Option<&str> {
+    let bytes = text.as_bytes();
+    if !bytes.first()?.is_ascii_alphabetic() {
+        return None;
+    }
+    let len = 1 + bytes[1..]
+        .iter()
+        .take_while(|b| b.is_ascii_alphanumeric() || **b == b'_')
+        .count();
+    Some(&text[len..])
+}
+
+/// `0|[1-9][0-9]*`
+fn is_index(text: &str) -> bool {
+    match text.as_bytes() {
+        [b'0'] => true,
+        [b'1'..=b'9', rest @ ..] => rest.iter().all(u8::is_ascii_digit),
+        _ => false,
+    }
+}
+
+fn is_ident(text: &str) -> bool {
+    strip_ident(text) == Some("")
+}
+
+fn is_key(text: &str) -> bool {
+    match strip_ident(text) {
+        Some("") => true,
+        Some(rest) => rest
+            .strip_prefix('[')
+            .and_then(|r| r.strip_suffix(']'))
+            .or_else(|| rest.strip_prefix('(').and_then(|r| r.strip_suffix(')')))
+            .is_some_and(is_index),
+        None => false,
+    }
+}
+
+fn is_object(text: &str) -> bool {
+    match strip_ident(text) {
+        Some("") => true,
+        Some(rest) => rest.strip_prefix([' ', '.']).is_some_and(is_ident),
+        None => false,
+    }
+}
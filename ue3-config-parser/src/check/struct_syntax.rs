@@ -1,4 +1,6 @@
-use std::iter::FusedIterator;
+use std::{borrow::Cow, convert::TryFrom, fmt, iter::FusedIterator, ops::Index};
+
+use crate::parse::Span;
 
 #[derive(Debug, Copy, Clone)]
 pub enum Token<'a> {
@@ -9,22 +11,41 @@ pub enum Token<'a> {
     Comma,
     Eq,
     Semi,
+    /// An unquoted name or number. Leading whitespace is skipped, but spaces and
+    /// (unless [`LexOptions::tab_ends_text`] is set) tabs after the first character
+    /// are part of the token, up to the next delimiter.
     Text(&'a str),
     Quoted(&'a str),
 }
 
+/// Options for [`tokenize_with`] and [`tokenize_spanned_with`]. The [`Default`]
+/// options tokenize the same way [`tokenize`] does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LexOptions {
+    /// End a [`Token::Text`] at a tab, as if it were a delimiter, so that
+    /// tab-separated sub-properties like `(A=1\tB=2)` become separate tokens
+    /// instead of a single `1\tB` text. Defaults to `false`.
+    pub tab_ends_text: bool,
+}
+
 struct Lexer<'a> {
     text: &'a str,
     last_pos: usize,
     it: std::iter::Peekable<std::str::CharIndices<'a>>,
+    options: LexOptions,
 }
 
 impl<'a> Lexer<'a> {
     fn new(text: &'a str) -> Self {
+        Self::with_options(text, LexOptions::default())
+    }
+
+    fn with_options(text: &'a str, options: LexOptions) -> Self {
         Self {
             text,
             last_pos: 0,
             it: text.char_indices().peekable(),
+            options,
         }
     }
 
@@ -48,7 +69,15 @@ impl<'a> Lexer<'a> {
                     end = p + 1;
                     break;
                 }
-                Some((p, c)) if !quoted && (matches!(c, '(' | ')' | '[' | ']' | ',' | '=' | '"' | ';')) => {
+                Some((_, '\\')) if quoted => {
+                    // Skip the escaped character
+                    self.it.next();
+                    self.it.next();
+                }
+                Some((p, c))
+                    if !quoted
+                        && (is_delimiter(*c) || self.options.tab_ends_text && *c == '\t') =>
+                {
                     end = *p;
                     break;
                 }
@@ -71,7 +100,56 @@ impl<'a> Lexer<'a> {
 }
 
 fn is_whitespace(i: char) -> bool {
-    return matches!(i, '\t' | ' ');
+    matches!(i, '\t' | ' ')
+}
+
+fn is_delimiter(i: char) -> bool {
+    matches!(i, '(' | ')' | '[' | ']' | ',' | '=' | '"' | ';')
+}
+
+/// Whether `s` must be quoted to be read back as a single terminal value.
+pub fn needs_quoting(s: &str) -> bool {
+    s.is_empty() || s.contains(|c| is_delimiter(c) || matches!(c, ' ' | '\t' | '\r' | '\n'))
+}
+
+/// Turn `s` into a terminal value, quoting and escaping it if needed.
+pub fn escape_value(s: &str) -> Cow<'_, str> {
+    if !needs_quoting(s) {
+        return Cow::Borrowed(s);
+    }
+
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        if matches!(c, '"' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped.push('"');
+    Cow::Owned(escaped)
+}
+
+/// The inverse of [`escape_value`] for a quoted string like [`Token::Quoted`]:
+/// removes the surrounding quotes and the backslashes of escape sequences.
+/// Only allocates if there are escape sequences.
+pub fn unescape_quoted(s: &str) -> Cow<'_, str> {
+    let s = s.strip_prefix('"').unwrap_or(s);
+    // An unterminated string lacks the closing quote
+    let s = s.strip_suffix('"').unwrap_or(s);
+    if !s.contains('\\') {
+        return Cow::Borrowed(s);
+    }
+
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    Cow::Owned(unescaped)
 }
 
 impl<'a> Iterator for Lexer<'a> {
@@ -105,10 +183,59 @@ impl<'a> Iterator for Lexer<'a> {
 // CharIndices is Fused, we are Fused as well.
 impl<'a> FusedIterator for Lexer<'a> {}
 
+/// Split a struct value into its tokens, skipping whitespace.
+///
+/// ```
+/// use ue3_config_parser::check::{tokenize, Token};
+///
+/// let tokens = tokenize(r#"(A="x")"#).collect::<Vec<_>>();
+/// assert!(matches!(
+///     tokens[..],
+///     [Token::LParen, Token::Text("A"), Token::Eq, Token::Quoted(r#""x""#), Token::RParen]
+/// ));
+/// ```
+pub fn tokenize(text: &str) -> impl FusedIterator<Item = Token<'_>> {
+    Lexer::new(text)
+}
+
+/// Like [`tokenize`], but additionally yields the byte span of every token in `text`.
+pub fn tokenize_spanned(text: &str) -> impl FusedIterator<Item = (Token<'_>, Span)> {
+    SpannedLexer(Lexer::new(text))
+}
+
+/// Like [`tokenize`], but with the given [`LexOptions`].
+pub fn tokenize_with(text: &str, options: LexOptions) -> impl FusedIterator<Item = Token<'_>> {
+    Lexer::with_options(text, options)
+}
+
+/// Like [`tokenize_spanned`], but with the given [`LexOptions`].
+pub fn tokenize_spanned_with(
+    text: &str,
+    options: LexOptions,
+) -> impl FusedIterator<Item = (Token<'_>, Span)> {
+    SpannedLexer(Lexer::with_options(text, options))
+}
+
+struct SpannedLexer<'a>(Lexer<'a>);
+
+impl<'a> Iterator for SpannedLexer<'a> {
+    type Item = (Token<'a>, Span);
+    fn next(&mut self) -> Option<Self::Item> {
+        let tok = self.0.next()?;
+        Some((tok, Span(self.0.last_pos, self.0.cur_pos())))
+    }
+}
+
+impl<'a> FusedIterator for SpannedLexer<'a> {}
+
 #[derive(Debug)]
 pub enum PropValue<'a> {
-    /// Name or 123 or 1.0 or "Something"
-    Terminal(&'a str),
+    /// Name or Name_1
+    Name(&'a str),
+    /// 123 or 1.0
+    Number(&'a str),
+    /// "Something", including the quotes
+    Str(&'a str),
     /// (A="123", B[0]=Name, C=1.0)
     Struct(Struct<'a>),
     /// (A, B, C)
@@ -117,10 +244,153 @@ pub enum PropValue<'a> {
     Empty,
 }
 
+impl<'a> PropValue<'a> {
+    /// Classify a `Text` or `Quoted` token.
+    fn terminal(tok: Token<'a>) -> Self {
+        match tok {
+            Token::Quoted(s) => PropValue::Str(s),
+            // Careful, `f64` also parses `inf` and `NaN`
+            Token::Text(s)
+                if s.starts_with(|c: char| c.is_ascii_digit() || matches!(c, '-' | '+' | '.'))
+                    && s.trim_end().parse::<f64>().is_ok() =>
+            {
+                PropValue::Number(s)
+            }
+            Token::Text(s) => PropValue::Name(s),
+            _ => unreachable!(),
+        }
+    }
+
+    /// The value of a number that is an integer.
+    pub fn as_i64(&self) -> Option<i64> {
+        match self {
+            PropValue::Number(s) => s.trim_end().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The value of a number.
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            PropValue::Number(s) => s.trim_end().parse().ok(),
+            _ => None,
+        }
+    }
+
+    /// The text of a string without its quotes, or of a name. Escape sequences
+    /// are not processed, see [`PropValue::content`] for that.
+    pub fn as_str(&self) -> Option<&'a str> {
+        match self {
+            PropValue::Str(s) => {
+                let s = &s[1..];
+                // An unterminated string lacks the closing quote
+                Some(s.strip_suffix('"').unwrap_or(s))
+            }
+            PropValue::Name(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// The text of a terminal value as written, or `None` for structs and arrays.
+    pub fn raw(&self) -> Option<&'a str> {
+        match self {
+            PropValue::Name(s) | PropValue::Number(s) | PropValue::Str(s) => Some(s),
+            PropValue::Struct(_) | PropValue::Array(_) | PropValue::Empty => None,
+        }
+    }
+
+    /// The text of a terminal value with escape sequences in strings processed
+    /// by [`unescape_quoted`].
+    pub fn content(&self) -> Option<Cow<'a, str>> {
+        match self {
+            PropValue::Str(s) => Some(unescape_quoted(s)),
+            _ => self.raw().map(Cow::Borrowed),
+        }
+    }
+
+    /// Like [`PropValue::content`], but without the trailing whitespace of
+    /// unquoted values.
+    fn eq_content(&self) -> Option<Cow<'a, str>> {
+        match self {
+            PropValue::Name(s) | PropValue::Number(s) => Some(Cow::Borrowed(s.trim_end())),
+            _ => self.content(),
+        }
+    }
+
+    /// The variant of this value without its data.
+    pub fn kind(&self) -> PropKind {
+        match self {
+            PropValue::Name(_) | PropValue::Number(_) | PropValue::Str(_) => PropKind::Terminal,
+            PropValue::Struct(_) => PropKind::Struct,
+            PropValue::Array(_) => PropKind::Array,
+            PropValue::Empty => PropKind::Empty,
+        }
+    }
+}
+
+/// The kind of a [`PropValue`], see [`PropValue::kind`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PropKind {
+    /// A name, number, or string
+    Terminal,
+    Struct,
+    Array,
+    Empty,
+}
+
+/// Compares structurally: terminals are equal if their content is, regardless
+/// of whether they are quoted, and property names are compared ignoring case.
+/// Whitespace the lexer keeps after unquoted names and values is ignored.
+impl<'a, 'b> PartialEq<PropValue<'b>> for PropValue<'a> {
+    fn eq(&self, other: &PropValue<'b>) -> bool {
+        match (self, other) {
+            (PropValue::Struct(a), PropValue::Struct(b)) => a == b,
+            (PropValue::Array(a), PropValue::Array(b)) => a == b,
+            (PropValue::Empty, PropValue::Empty) => true,
+            (a, b) => match (a.eq_content(), b.eq_content()) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// The brackets around the index of a [`PropName`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexStyle {
+    /// `Name[0]`
+    Bracket,
+    /// `Name(0)`
+    Paren,
+}
+
 #[derive(Debug)]
 pub struct PropName<'a> {
-    name: &'a str,
-    idx: Option<u32>,
+    pub name: &'a str,
+    /// The index of `Name[0]` or `Name(0)`
+    pub idx: Option<u64>,
+    /// How the index was written. Not considered when comparing names.
+    pub index_style: IndexStyle,
+}
+
+impl fmt::Display for PropName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name)?;
+        match (self.idx, self.index_style) {
+            (Some(idx), IndexStyle::Bracket) => write!(f, "[{}]", idx),
+            (Some(idx), IndexStyle::Paren) => write!(f, "({})", idx),
+            (None, _) => Ok(()),
+        }
+    }
+}
+
+impl<'a, 'b> PartialEq<PropName<'b>> for PropName<'a> {
+    fn eq(&self, other: &PropName<'b>) -> bool {
+        self.name
+            .trim_end()
+            .eq_ignore_ascii_case(other.name.trim_end())
+            && self.idx == other.idx
+    }
 }
 
 #[derive(Debug)]
@@ -128,20 +398,213 @@ pub struct Struct<'a> {
     pub children: Vec<(PropName<'a>, PropValue<'a>)>,
 }
 
+impl<'a, 'b> PartialEq<Struct<'b>> for Struct<'a> {
+    fn eq(&self, other: &Struct<'b>) -> bool {
+        self.children.len() == other.children.len()
+            && self
+                .children
+                .iter()
+                .zip(&other.children)
+                .all(|((an, av), (bn, bv))| an == bn && av == bv)
+    }
+}
+
 #[derive(Debug)]
 pub struct Array<'a> {
     pub elems: Vec<PropValue<'a>>,
 }
 
+/// Walks a parsed struct value. [`Struct::accept`] calls `visit_struct` and
+/// `visit_array` before recursing into the children, and `visit_terminal` for
+/// every name, number, and string.
+pub trait StructVisitor<'a> {
+    fn visit_terminal(&mut self, _value: &PropValue<'a>) {}
+    fn visit_struct(&mut self, _value: &Struct<'a>) {}
+    fn visit_array(&mut self, _value: &Array<'a>) {}
+}
+
+impl<'a> Struct<'a> {
+    /// The value of the child `name` without an index, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&PropValue<'a>> {
+        self.children
+            .iter()
+            .find(|(n, _)| n.idx.is_none() && n.name.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    /// The value of the child `name[idx]`, ignoring case.
+    pub fn get_indexed(&self, name: &str, idx: u64) -> Option<&PropValue<'a>> {
+        self.children
+            .iter()
+            .find(|(n, _)| n.idx == Some(idx) && n.name.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v)
+    }
+
+    /// Look up a nested value by a path like `NewCost.ResourceCosts[0].Quantity`.
+    ///
+    /// `Name[n]` refers to either the child `Name[n]` or the `n`th element of
+    /// the array `Name`. Returns `None` if any segment doesn't match.
+    pub fn get_path(&self, path: &str) -> Option<&PropValue<'a>> {
+        let mut current = self;
+        let mut segments = path.split('.').peekable();
+        while let Some(segment) = segments.next() {
+            let value = match segment.strip_suffix(']') {
+                Some(indexed) => {
+                    let (name, idx) = indexed.split_once('[')?;
+                    let idx = idx.parse().ok()?;
+                    match current.get_indexed(name, idx) {
+                        Some(value) => value,
+                        None => match current.get(name)? {
+                            PropValue::Array(a) => a.get(usize::try_from(idx).ok()?)?,
+                            _ => return None,
+                        },
+                    }
+                }
+                None => current.get(segment)?,
+            };
+            if segments.peek().is_none() {
+                return Some(value);
+            }
+            current = match value {
+                PropValue::Struct(s) => s,
+                _ => return None,
+            };
+        }
+        None
+    }
+
+    pub fn accept(&self, visitor: &mut dyn StructVisitor<'a>) {
+        visitor.visit_struct(self);
+        for (_, value) in &self.children {
+            value.accept(visitor);
+        }
+    }
+}
+
+impl<'a> Array<'a> {
+    pub fn len(&self) -> usize {
+        self.elems.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elems.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&PropValue<'a>> {
+        self.elems.get(index)
+    }
+
+    pub fn accept(&self, visitor: &mut dyn StructVisitor<'a>) {
+        visitor.visit_array(self);
+        for value in &self.elems {
+            value.accept(visitor);
+        }
+    }
+}
+
+/// Writes the value back as struct syntax. Terminals are written as they
+/// appeared in the parsed text, while whitespace and trailing commas are not
+/// preserved.
+impl fmt::Display for PropValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PropValue::Name(s) | PropValue::Number(s) | PropValue::Str(s) => f.write_str(s),
+            PropValue::Struct(s) => s.fmt(f),
+            PropValue::Array(a) => a.fmt(f),
+            PropValue::Empty => f.write_str("()"),
+        }
+    }
+}
+
+impl fmt::Display for Struct<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(")?;
+        for (i, (name, value)) in self.children.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}={}", name, value)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl fmt::Display for Array<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("(")?;
+        for (i, value) in self.elems.iter().enumerate() {
+            if i > 0 {
+                f.write_str(",")?;
+            }
+            value.fmt(f)?;
+        }
+        f.write_str(")")
+    }
+}
+
+impl<'a, 'b> PartialEq<Array<'b>> for Array<'a> {
+    fn eq(&self, other: &Array<'b>) -> bool {
+        self.elems.len() == other.elems.len()
+            && self.elems.iter().zip(&other.elems).all(|(a, b)| a == b)
+    }
+}
+
+impl<'a> Index<usize> for Array<'a> {
+    type Output = PropValue<'a>;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        &self.elems[index]
+    }
+}
+
+impl<'a> PropValue<'a> {
+    pub fn accept(&self, visitor: &mut dyn StructVisitor<'a>) {
+        match self {
+            PropValue::Name(_) | PropValue::Number(_) | PropValue::Str(_) => {
+                visitor.visit_terminal(self)
+            }
+            PropValue::Struct(s) => s.accept(visitor),
+            PropValue::Array(a) => a.accept(visitor),
+            PropValue::Empty => {}
+        }
+    }
+}
+
+/// A token the parser would have accepted at the position of a [`ParseError`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExpectedToken {
+    LParen,
+    RParen,
+    LBrack,
+    RBrack,
+    Comma,
+    Eq,
+    /// A property name.
+    Name,
+    /// A name, number, or string.
+    Value,
+    /// An array index like the `0` in `Name[0]`.
+    Index,
+    /// The end of the text.
+    End,
+}
+
 #[derive(Debug)]
 pub struct ParseError {
     pub pos: usize,
+    /// A description of the error for display.
     pub msg: String,
+    /// The tokens that would have been valid at `pos`.
+    pub expected: Vec<ExpectedToken>,
 }
 
 impl ParseError {
-    fn new(pos: usize, msg: String) -> Self {
-        Self { pos, msg }
+    fn new(pos: usize, msg: String, expected: &[ExpectedToken]) -> Self {
+        Self {
+            pos,
+            msg,
+            expected: expected.to_vec(),
+        }
     }
 }
 
@@ -167,28 +630,42 @@ impl<'a> Parser<'a> {
     }
 }
 
-pub fn parse(text: &str) -> Result<Struct<'_>, ParseError> {
+/// Parse a property value: a struct, an array, or a single name, number, or string.
+///
+/// ```
+/// use ue3_config_parser::{parse_value, PropValue};
+///
+/// let value = parse_value(r#"(A=1, B="x")"#).unwrap();
+/// let s = match value {
+///     PropValue::Struct(s) => s,
+///     _ => unreachable!(),
+/// };
+/// assert_eq!(s.children.len(), 2);
+/// assert_eq!(s.children[1].0.name, "B");
+/// assert!(matches!(s.children[1].1, PropValue::Str(r#""x""#)));
+/// ```
+pub fn parse_value(text: &str) -> Result<PropValue<'_>, ParseError> {
     let lexer = Lexer::new(text);
     let mut parser = Parser {
         lexer,
         peeked: None,
     };
     let result = match parser.next() {
-        Some(Token::LParen) => match parser.next() {
-            Some(t @ Token::Text(_)) => parse_struct(&mut parser, t)?,
-            _ => {
-                return Err(ParseError::new(
-                    parser.pos(),
-                    "Expected property name".to_owned(),
-                ))
-            }
-        },
-        _ => return Err(ParseError::new(parser.pos(), "Expected `(`".to_owned())),
+        Some(t @ (Token::Text(_) | Token::Quoted(_))) => PropValue::terminal(t),
+        Some(Token::LParen) => parse_struct_or_array(&mut parser)?,
+        _ => {
+            return Err(ParseError::new(
+                parser.pos(),
+                "Expected `(` or value".to_owned(),
+                &[ExpectedToken::LParen, ExpectedToken::Value],
+            ))
+        }
     };
     match parser.next() {
         Some(_) => Err(ParseError::new(
             parser.pos(),
             "Expected end of tokens".to_owned(),
+            &[ExpectedToken::End],
         )),
         None => Ok(result),
     }
@@ -198,14 +675,20 @@ pub fn parse(text: &str) -> Result<Struct<'_>, ParseError> {
 fn parse_array<'a>(parser: &mut Parser<'a>, ex_token: Token<'a>) -> Result<Array<'a>, ParseError> {
     let mut elems = vec![];
     match ex_token {
-        Token::Text(s) | Token::Quoted(s) => elems.push(PropValue::Terminal(s)),
+        t @ (Token::Text(_) | Token::Quoted(_)) => elems.push(PropValue::terminal(t)),
         Token::LParen => {
             match parser.next() {
                 Some(t @ Token::Text(_)) => {
                     // Nested arrays don't exist, so arrays contain either terminals or structs
                     elems.push(PropValue::Struct(parse_struct(parser, t)?))
                 }
-                _ => return Err(ParseError::new(parser.pos(), "expected name".to_owned())),
+                _ => {
+                    return Err(ParseError::new(
+                        parser.pos(),
+                        "expected name".to_owned(),
+                        &[ExpectedToken::Name],
+                    ))
+                }
             }
         }
         _ => unreachable!(),
@@ -223,7 +706,8 @@ fn parse_array<'a>(parser: &mut Parser<'a>, ex_token: Token<'a>) -> Result<Array
             _ => {
                 return Err(ParseError::new(
                     parser.pos(),
-                    "expected `,` or `(`".to_owned(),
+                    "expected `,` or `)`".to_owned(),
+                    &[ExpectedToken::Comma, ExpectedToken::RParen],
                 ))
             }
         }
@@ -232,17 +716,33 @@ fn parse_array<'a>(parser: &mut Parser<'a>, ex_token: Token<'a>) -> Result<Array
             Some(Token::RParen) => {
                 break;
             }
-            Some(Token::Text(s) | Token::Quoted(s)) => elems.push(PropValue::Terminal(s)),
+            Some(t @ (Token::Text(_) | Token::Quoted(_))) => elems.push(PropValue::terminal(t)),
             Some(Token::LParen) => {
                 match parser.next() {
                     Some(t @ Token::Text(_)) => {
                         // Nested arrays don't exist, so arrays contain either terminals or structs
                         elems.push(PropValue::Struct(parse_struct(parser, t)?))
                     }
-                    _ => return Err(ParseError::new(parser.pos(), "expected name".to_owned())),
+                    _ => {
+                        return Err(ParseError::new(
+                            parser.pos(),
+                            "expected name".to_owned(),
+                            &[ExpectedToken::Name],
+                        ))
+                    }
                 }
             }
-            _ => return Err(ParseError::new(parser.pos(), "expected value".to_owned())),
+            _ => {
+                return Err(ParseError::new(
+                    parser.pos(),
+                    "expected value".to_owned(),
+                    &[
+                        ExpectedToken::RParen,
+                        ExpectedToken::LParen,
+                        ExpectedToken::Value,
+                    ],
+                ))
+            }
         }
     }
 
@@ -264,27 +764,48 @@ fn parse_struct<'a>(
             _ => unreachable!(),
         };
 
+        let mut index_style = IndexStyle::Bracket;
         let idx = match parser.peek() {
-            Some(Token::LBrack) => {
-                parser.next();
+            Some(Token::LBrack | Token::LParen) => {
+                // Both `Name[0]` and `Name(0)` are valid index forms
+                let bracket = matches!(parser.next(), Some(Token::LBrack));
+                if !bracket {
+                    index_style = IndexStyle::Paren;
+                }
                 if let Some(Token::Text(t)) = parser.next() {
-                    match t.parse::<u32>() {
+                    match t.parse::<u64>() {
                         Ok(idx) => {
-                            match parser.next() {
-                                Some(Token::RBrack) => {}
-                                Some(_) | None => {
+                            match (parser.next(), bracket) {
+                                (Some(Token::RBrack), true) | (Some(Token::RParen), false) => {}
+                                (_, true) => {
                                     return Err(ParseError::new(
                                         parser.pos(),
                                         "Expected `]`".to_owned(),
+                                        &[ExpectedToken::RBrack],
+                                    ))
+                                }
+                                (_, false) => {
+                                    return Err(ParseError::new(
+                                        parser.pos(),
+                                        "Expected `)`".to_owned(),
+                                        &[ExpectedToken::RParen],
                                     ))
                                 }
                             }
                             Some(idx)
                         }
+                        Err(_) if !t.is_empty() && t.bytes().all(|b| b.is_ascii_digit()) => {
+                            return Err(ParseError::new(
+                                parser.pos(),
+                                "array index too large".to_owned(),
+                                &[ExpectedToken::Index],
+                            ))
+                        }
                         Err(_) => {
                             return Err(ParseError::new(
                                 parser.pos(),
                                 "Expected array index".to_owned(),
+                                &[ExpectedToken::Index],
                             ))
                         }
                     }
@@ -292,6 +813,7 @@ fn parse_struct<'a>(
                     return Err(ParseError::new(
                         parser.pos(),
                         "Expected array index".to_owned(),
+                        &[ExpectedToken::Index],
                     ));
                 }
             }
@@ -300,16 +822,32 @@ fn parse_struct<'a>(
 
         match parser.next() {
             Some(Token::Eq) => {}
-            _ => return Err(ParseError::new(parser.pos(), "Expected `=`".to_owned())),
+            _ => {
+                // An index is only allowed if there isn't one already
+                let expected: &[ExpectedToken] = match idx {
+                    Some(_) => &[ExpectedToken::Eq],
+                    None => &[
+                        ExpectedToken::LBrack,
+                        ExpectedToken::LParen,
+                        ExpectedToken::Eq,
+                    ],
+                };
+                return Err(ParseError::new(
+                    parser.pos(),
+                    "Expected `=`".to_owned(),
+                    expected,
+                ));
+            }
         }
 
         let val = match parser.next() {
-            Some(Token::Text(s) | Token::Quoted(s)) => PropValue::Terminal(s),
+            Some(t @ (Token::Text(_) | Token::Quoted(_))) => PropValue::terminal(t),
             Some(Token::LParen) => parse_struct_or_array(parser)?,
             _ => {
                 return Err(ParseError::new(
                     parser.pos(),
                     "Expected `(` or value".to_owned(),
+                    &[ExpectedToken::LParen, ExpectedToken::Value],
                 ))
             }
         };
@@ -318,6 +856,7 @@ fn parse_struct<'a>(
             PropName {
                 name: prop_name,
                 idx,
+                index_style,
             },
             val,
         ));
@@ -329,6 +868,7 @@ fn parse_struct<'a>(
                 return Err(ParseError::new(
                     parser.pos(),
                     "Expected `,` or `)`".to_owned(),
+                    &[ExpectedToken::Comma, ExpectedToken::RParen],
                 ))
             }
         }
@@ -340,6 +880,7 @@ fn parse_struct<'a>(
                 return Err(ParseError::new(
                     parser.pos(),
                     "Expected `)` or name".to_owned(),
+                    &[ExpectedToken::RParen, ExpectedToken::Name],
                 ))
             }
         }
@@ -356,12 +897,17 @@ fn parse_struct_or_array<'a>(parser: &mut Parser<'a>) -> Result<PropValue<'a>, P
             return Err(ParseError::new(
                 parser.pos(),
                 "Expected name, value, or `)`".to_owned(),
+                &[
+                    ExpectedToken::LParen,
+                    ExpectedToken::RParen,
+                    ExpectedToken::Value,
+                ],
             ))
         }
     };
 
     match (prop_token, parser.peek()) {
-        (Token::Text(_), Some(Token::Eq | Token::LBrack)) => {
+        (Token::Text(_), Some(Token::Eq | Token::LBrack | Token::LParen)) => {
             // `prop_token` is the property name of a KVP, followed by optional index and equals sign
             parse_struct(parser, prop_token).map(PropValue::Struct)
         }
@@ -373,18 +919,48 @@ fn parse_struct_or_array<'a>(parser: &mut Parser<'a>) -> Result<PropValue<'a>, P
             // `prop_token` is the opening paren of a struct array element
             parse_array(parser, prop_token).map(PropValue::Array)
         }
-        _ => Err(ParseError::new(
-            parser.pos(),
-            "Expected key-value pair or array value`".to_owned(),
-        )),
+        (prop_token, _) => {
+            let expected: &[ExpectedToken] = match prop_token {
+                Token::Text(_) => &[
+                    ExpectedToken::LBrack,
+                    ExpectedToken::LParen,
+                    ExpectedToken::Eq,
+                    ExpectedToken::Comma,
+                    ExpectedToken::RParen,
+                ],
+                Token::Quoted(_) => &[ExpectedToken::Comma, ExpectedToken::RParen],
+                Token::LParen => &[ExpectedToken::Name, ExpectedToken::RParen],
+                _ => &[],
+            };
+            Err(ParseError::new(
+                parser.pos(),
+                "Expected key-value pair or array value`".to_owned(),
+                expected,
+            ))
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::borrow::Cow;
+
     use expect_test::{expect, expect_file};
 
-    use super::{parse, Lexer, Token};
+    use super::{
+        escape_value, needs_quoting, parse_value, tokenize, tokenize_spanned,
+        tokenize_spanned_with, tokenize_with, unescape_quoted, Array, ExpectedToken, LexOptions,
+        Lexer, ParseError, PropKind, PropValue, Struct, StructVisitor, Token,
+    };
+    use crate::parse::Span;
+
+    /// Parse `text`, which must be a struct if it parses at all.
+    fn parse(text: &str) -> Result<Struct<'_>, ParseError> {
+        parse_value(text).map(|value| match value {
+            PropValue::Struct(s) => s,
+            other => panic!("expected a struct, got {:?}", other),
+        })
+    }
 
     #[test]
     fn test_ok_tokens() {
@@ -421,8 +997,9 @@ mod tests {
                             PropName {
                                 name: "Prop1",
                                 idx: None,
+                                index_style: Bracket,
                             },
-                            Terminal(
+                            Number(
                                 "1.0",
                             ),
                         ),
@@ -430,8 +1007,9 @@ mod tests {
                             PropName {
                                 name: "Prop2",
                                 idx: None,
+                                index_style: Bracket,
                             },
-                            Terminal(
+                            Str(
                                 "\"Abc\"",
                             ),
                         ),
@@ -442,6 +1020,310 @@ mod tests {
         expect.assert_debug_eq(&parse(test_string));
     }
 
+    #[test]
+    fn test_spans() {
+        let test_string = r#"(A=1)"#;
+        let eq = tokenize_spanned(test_string).find(|(t, _)| matches!(t, Token::Eq));
+        let expect = expect![[r#"
+            Some(
+                (
+                    Eq,
+                    Span(
+                        2,
+                        3,
+                    ),
+                ),
+            )
+        "#]];
+        expect.assert_debug_eq(&eq);
+
+        let test_string = r#"( Abc , "D" )"#;
+        let tokens = tokenize_spanned(test_string)
+            .map(|(_, sp)| &test_string[sp])
+            .collect::<Vec<_>>();
+        let expect = expect![[r#"
+            [
+                "(",
+                "Abc ",
+                ",",
+                "\"D\"",
+                ")",
+            ]
+        "#]];
+        expect.assert_debug_eq(&tokens);
+    }
+
+    #[test]
+    fn test_escape() {
+        assert!(!needs_quoting("Supplies"));
+        assert!(!needs_quoting("1.0"));
+        assert!(needs_quoting("Hello World"));
+        assert!(needs_quoting(""));
+
+        assert_eq!(escape_value("Supplies"), "Supplies");
+        assert_eq!(escape_value("Hello World"), r#""Hello World""#);
+        assert_eq!(escape_value("(A=B)"), r#""(A=B)""#);
+        let escaped = escape_value(r#"Say "hi""#);
+        assert_eq!(escaped, r#""Say \"hi\"""#);
+
+        let tokens = tokenize(&escaped).collect::<Vec<_>>();
+        let expect = expect![[r#"
+            [
+                Quoted(
+                    "\"Say \\\"hi\\\"\"",
+                ),
+            ]
+        "#]];
+        expect.assert_debug_eq(&tokens);
+    }
+
+    #[test]
+    fn test_terminals() {
+        let test_string = r#"(A="A", B=A, C=5, D=-1.5, E=(A, "A", 5, inf))"#;
+        let expect = expect![[r#"
+            Ok(
+                Struct {
+                    children: [
+                        (
+                            PropName {
+                                name: "A",
+                                idx: None,
+                                index_style: Bracket,
+                            },
+                            Str(
+                                "\"A\"",
+                            ),
+                        ),
+                        (
+                            PropName {
+                                name: "B",
+                                idx: None,
+                                index_style: Bracket,
+                            },
+                            Name(
+                                "A",
+                            ),
+                        ),
+                        (
+                            PropName {
+                                name: "C",
+                                idx: None,
+                                index_style: Bracket,
+                            },
+                            Number(
+                                "5",
+                            ),
+                        ),
+                        (
+                            PropName {
+                                name: "D",
+                                idx: None,
+                                index_style: Bracket,
+                            },
+                            Number(
+                                "-1.5",
+                            ),
+                        ),
+                        (
+                            PropName {
+                                name: "E",
+                                idx: None,
+                                index_style: Bracket,
+                            },
+                            Array(
+                                Array {
+                                    elems: [
+                                        Name(
+                                            "A",
+                                        ),
+                                        Str(
+                                            "\"A\"",
+                                        ),
+                                        Number(
+                                            "5",
+                                        ),
+                                        Name(
+                                            "inf",
+                                        ),
+                                    ],
+                                },
+                            ),
+                        ),
+                    ],
+                },
+            )
+        "#]];
+        expect.assert_debug_eq(&parse(test_string));
+    }
+
+    #[test]
+    fn test_paren_index() {
+        let test_string = r#"(Foo(1)=x, Bar=(Baz(0)=1))"#;
+        let expect = expect![[r#"
+            Ok(
+                Struct {
+                    children: [
+                        (
+                            PropName {
+                                name: "Foo",
+                                idx: Some(
+                                    1,
+                                ),
+                                index_style: Paren,
+                            },
+                            Name(
+                                "x",
+                            ),
+                        ),
+                        (
+                            PropName {
+                                name: "Bar",
+                                idx: None,
+                                index_style: Bracket,
+                            },
+                            Struct(
+                                Struct {
+                                    children: [
+                                        (
+                                            PropName {
+                                                name: "Baz",
+                                                idx: Some(
+                                                    0,
+                                                ),
+                                                index_style: Paren,
+                                            },
+                                            Number(
+                                                "1",
+                                            ),
+                                        ),
+                                    ],
+                                },
+                            ),
+                        ),
+                    ],
+                },
+            )
+        "#]];
+        expect.assert_debug_eq(&parse(test_string));
+
+        let expect = expect![[r#"
+            Err(
+                ParseError {
+                    pos: 6,
+                    msg: "Expected `)`",
+                    expected: [
+                        RParen,
+                    ],
+                },
+            )
+        "#]];
+        expect.assert_debug_eq(&parse("(Foo(1]=x)"));
+    }
+
+    #[test]
+    fn test_empty_string() {
+        let tokens = tokenize_spanned(r#"(Name="")"#).collect::<Vec<_>>();
+        assert!(matches!(tokens[3], (Token::Quoted(r#""""#), Span(6, 8))));
+
+        let value = parse(r#"(Name="", Other="x", Name=Abc)"#).unwrap();
+        let strings = value
+            .children
+            .iter()
+            .map(|(_, v)| v.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(strings, [Some(""), Some("x"), Some("Abc")]);
+    }
+
+    #[test]
+    fn test_array_access() {
+        let arr = match parse_value("(0, 1 ,2.5)").unwrap() {
+            PropValue::Array(arr) => arr,
+            _ => unreachable!(),
+        };
+        assert_eq!(arr.len(), 3);
+        assert!(!arr.is_empty());
+        assert_eq!(arr[1].as_i64(), Some(1));
+        assert_eq!(arr[2].as_i64(), None);
+        assert_eq!(arr.get(2).and_then(PropValue::as_f64), Some(2.5));
+        assert!(arr.get(3).is_none());
+    }
+
+    #[test]
+    fn test_large_index() {
+        let expect = expect![[r#"
+            Ok(
+                Struct {
+                    children: [
+                        (
+                            PropName {
+                                name: "Foo",
+                                idx: Some(
+                                    4294967296,
+                                ),
+                                index_style: Bracket,
+                            },
+                            Name(
+                                "x",
+                            ),
+                        ),
+                    ],
+                },
+            )
+        "#]];
+        expect.assert_debug_eq(&parse("(Foo[4294967296]=x)"));
+
+        let expect = expect![[r#"
+            Err(
+                ParseError {
+                    pos: 5,
+                    msg: "array index too large",
+                    expected: [
+                        Index,
+                    ],
+                },
+            )
+        "#]];
+        expect.assert_debug_eq(&parse("(Foo[18446744073709551616]=x)"));
+
+        let expect = expect![[r#"
+            Err(
+                ParseError {
+                    pos: 5,
+                    msg: "Expected array index",
+                    expected: [
+                        Index,
+                    ],
+                },
+            )
+        "#]];
+        expect.assert_debug_eq(&parse("(Foo[abc]=x)"));
+    }
+
+    #[test]
+    fn tab_separated() {
+        let test_string = "(Template=\"A\",\t\tMin=3\tMax=7 ,\tName=Some Name\t)";
+        let tokens = tokenize(test_string).collect::<Vec<_>>();
+        let expect = expect![[
+            r#"[LParen, Text("Template"), Eq, Quoted("\"A\""), Comma, Text("Min"), Eq, Text("3\tMax"), Eq, Text("7 "), Comma, Text("Name"), Eq, Text("Some Name\t"), RParen]"#
+        ]];
+        expect.assert_eq(&format!("{:?}", tokens));
+
+        let options = LexOptions {
+            tab_ends_text: true,
+        };
+        let tokens = tokenize_with(test_string, options).collect::<Vec<_>>();
+        let expect = expect![[
+            r#"[LParen, Text("Template"), Eq, Quoted("\"A\""), Comma, Text("Min"), Eq, Text("3"), Text("Max"), Eq, Text("7 "), Comma, Text("Name"), Eq, Text("Some Name"), RParen]"#
+        ]];
+        expect.assert_eq(&format!("{:?}", tokens));
+
+        for (tok, span) in tokenize_spanned_with(test_string, options) {
+            if let Token::Text(s) = tok {
+                assert_eq!(&test_string[span], s);
+            }
+        }
+    }
+
     #[test]
     fn test_small() {
         let test_string = r#"(Prop1=1.0, Prop2[0]=(T="A", W=5),)"#;
@@ -491,10 +1373,18 @@ mod tests {
                 ParseError {
                     pos: 10,
                     msg: "Expected `,` or `)`",
+                    expected: [
+                        Comma,
+                        RParen,
+                    ],
                 },
             )
         "#]];
         expect.assert_debug_eq(&parse(test_string));
+        assert_eq!(
+            parse(test_string).unwrap_err().expected,
+            [ExpectedToken::Comma, ExpectedToken::RParen]
+        );
     }
 
     #[test]
@@ -512,6 +1402,32 @@ mod tests {
             "/test_data/struct/exciting_parsed.txt"
         )];
         expect.assert_debug_eq(&parse(test_string));
+
+        #[derive(Default)]
+        struct Counter {
+            terminals: usize,
+            structs: usize,
+            arrays: usize,
+        }
+
+        impl<'a> StructVisitor<'a> for Counter {
+            fn visit_terminal(&mut self, _value: &PropValue<'a>) {
+                self.terminals += 1;
+            }
+            fn visit_struct(&mut self, _value: &Struct<'a>) {
+                self.structs += 1;
+            }
+            fn visit_array(&mut self, _value: &Array<'a>) {
+                self.arrays += 1;
+            }
+        }
+
+        let mut counter = Counter::default();
+        parse(test_string).unwrap().accept(&mut counter);
+        assert_eq!(
+            (counter.terminals, counter.structs, counter.arrays),
+            (6, 3, 1)
+        );
     }
 
     #[test]
@@ -539,6 +1455,9 @@ mod tests {
                 ParseError {
                     pos: 7,
                     msg: "Expected end of tokens",
+                    expected: [
+                        End,
+                    ],
                 },
             )
         "#]];
@@ -596,8 +1515,9 @@ mod tests {
                             PropName {
                                 name: "DeckName",
                                 idx: None,
+                                index_style: Bracket,
                             },
-                            Terminal(
+                            Str(
                                 "\"YpresXComAbilitiesT1\"",
                             ),
                         ),
@@ -605,6 +1525,7 @@ mod tests {
                             PropName {
                                 name: "Abilities",
                                 idx: None,
+                                index_style: Bracket,
                             },
                             Array(
                                 Array {
@@ -616,8 +1537,9 @@ mod tests {
                                                         PropName {
                                                             name: "AbilityName",
                                                             idx: None,
+                                                            index_style: Bracket,
                                                         },
-                                                        Terminal(
+                                                        Str(
                                                             "\"EverVigilant\"",
                                                         ),
                                                     ),
@@ -625,8 +1547,9 @@ mod tests {
                                                         PropName {
                                                             name: "ApplyToWeaponSlot",
                                                             idx: None,
+                                                            index_style: Bracket,
                                                         },
-                                                        Terminal(
+                                                        Name(
                                                             "eInvSlot_PrimaryWeapon",
                                                         ),
                                                     ),
@@ -643,4 +1566,108 @@ mod tests {
         "#]];
         expect.assert_debug_eq(&parse(test_string));
     }
+
+    #[test]
+    fn structural_eq() {
+        let value = |s| parse_value(s).unwrap();
+        assert_eq!(value("(A=1,B=2)"), value("(A=1,B=2)"));
+        assert_ne!(value("(A=1,B=2)"), value("(A=1,B=3)"));
+        assert_ne!(value("(A=1,B=2)"), value("(B=2,A=1)"));
+        assert_ne!(value("(A=1)"), value("(A=1,B=2)"));
+        assert_eq!(value("(a=1, b[0]=2)"), value("(A=1,B[0]=2)"));
+        assert_ne!(value("(B[0]=2)"), value("(B[1]=2)"));
+        assert_eq!(
+            value(r#"(Name="X", List=(A,"B"))"#),
+            value("(Name=X,List=(\"A\",B))")
+        );
+        assert_eq!(value(r#""say \"hi\"""#), value(r#""say \"h\i\"""#));
+        assert_ne!(value("Name"), value("name"));
+        assert_eq!(value("()"), value("()"));
+        assert_ne!(value("()"), value("(A)"));
+    }
+
+    #[test]
+    fn get_path() {
+        let test_string = r#"(ItemName="EMPGrenadeMk2", Difficulties=(0,1,2), NewCost=(ResourceCosts[0]=(ItemTemplateName="Supplies", Quantity=25)), Items=((A=1),(A=2)))"#;
+        let parsed = parse(test_string).unwrap();
+        let get = |path| parsed.get_path(path).and_then(PropValue::raw);
+
+        assert_eq!(get("NewCost.ResourceCosts[0].Quantity"), Some("25"));
+        assert_eq!(
+            get("newcost.resourcecosts[0].itemtemplatename"),
+            Some(r#""Supplies""#)
+        );
+        assert_eq!(get("Difficulties[2]"), Some("2"));
+        assert_eq!(get("Items[1].A"), Some("2"));
+        assert_eq!(get("ItemName"), Some(r#""EMPGrenadeMk2""#));
+
+        assert_eq!(get("NewCost.ResourceCosts[1].Quantity"), None);
+        assert_eq!(get("NewCost.ResourceCosts.Quantity"), None);
+        assert_eq!(get("ItemName.Length"), None);
+        assert_eq!(get("Difficulties[x]"), None);
+        assert_eq!(get(""), None);
+    }
+
+    #[test]
+    fn trailing_commas() {
+        for (text, canonical) in [
+            ("(0,1,2,)", "(0,1,2)"),
+            ("(A=1,)", "(A=1)"),
+            ("((A=1),(A=2),)", "((A=1),(A=2))"),
+            ("(A=(1,2,), B=(C=1,),)", "(A=(1,2), B=(C=1))"),
+        ] {
+            let value = parse_value(text).unwrap();
+            assert_eq!(value.to_string(), canonical);
+            assert_eq!(value, parse_value(canonical).unwrap());
+        }
+        // Only a single trailing comma is allowed
+        assert!(parse_value("(0,1,,)").is_err());
+        assert!(parse_value("(A=1,,)").is_err());
+        assert!(parse_value("(,)").is_err());
+    }
+
+    #[test]
+    fn kind() {
+        let value = parse_value(r#"(A=Name, B=1.5, C="Str", D=(X=1), E=(1, 2), F=())"#).unwrap();
+        let s = match value {
+            PropValue::Struct(s) => s,
+            _ => unreachable!(),
+        };
+        let kinds = s.children.iter().map(|(_, v)| v.kind()).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            [
+                PropKind::Terminal,
+                PropKind::Terminal,
+                PropKind::Terminal,
+                PropKind::Struct,
+                PropKind::Array,
+                PropKind::Empty,
+            ]
+        );
+        assert_eq!(parse_value("Name").unwrap().kind(), PropKind::Terminal);
+    }
+
+    #[test]
+    fn display_round_trip() {
+        for text in [
+            "(Foo(1)=x)",
+            "(Foo[1]=x, Bar=(A=\"1\", B=()), List=(1,2.5,Name), Structs=((A=1),(A=2)))",
+        ] {
+            assert_eq!(parse(text).unwrap().to_string(), text);
+        }
+        assert_eq!(parse("(Foo(1)=x)").unwrap(), parse("(Foo[1]=x)").unwrap());
+    }
+
+    #[test]
+    fn unescape() {
+        assert!(matches!(
+            unescape_quoted("\"plain\""),
+            Cow::Borrowed("plain")
+        ));
+        assert_eq!(unescape_quoted("\"a\\\"b\""), "a\"b");
+        assert_eq!(unescape_quoted("\"C:\\\\Dir\\\\\""), "C:\\Dir\\");
+        assert_eq!(unescape_quoted("\"unterminated"), "unterminated");
+        assert_eq!(unescape_quoted(&escape_value("say \"hi\"")), "say \"hi\"");
+    }
 }
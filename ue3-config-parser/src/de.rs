@@ -0,0 +1,334 @@
+//! Deserialize parsed struct values into `serde` types.
+
+use std::{borrow::Cow, fmt};
+
+use serde::de::{
+    self, value::StrDeserializer, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess,
+    SeqAccess, Visitor,
+};
+
+use crate::check::struct_syntax::{PropValue, Struct};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+/// Deserialize a parsed struct into `T`.
+///
+/// Children are matched to fields ignoring case, and indexed children like
+/// `Costs[0]=...` are collected into a sequence. Arrays become sequences, and
+/// names, numbers, and strings become scalars. Escape sequences in strings are
+/// processed with [`crate::check::unescape_quoted`], so strings only borrow
+/// from the text if they don't contain any.
+pub fn from_struct<'de, T: de::Deserialize<'de>>(value: &Struct<'de>) -> Result<T, Error> {
+    T::deserialize(ValueDeserializer::Struct(value))
+}
+
+/// Like [`from_struct`], but for any property value.
+pub fn from_value<'de, T: de::Deserialize<'de>>(value: &PropValue<'de>) -> Result<T, Error> {
+    T::deserialize(ValueDeserializer::Value(value))
+}
+
+enum ValueDeserializer<'a, 'de> {
+    Value(&'a PropValue<'de>),
+    Struct(&'a Struct<'de>),
+    /// All children with the same name that have an index, in index order.
+    Indexed(Vec<&'a PropValue<'de>>),
+}
+
+impl<'a, 'de> ValueDeserializer<'a, 'de> {
+    fn terminal(&self) -> Option<Cow<'de, str>> {
+        match self {
+            ValueDeserializer::Value(PropValue::Number(s)) => Some(Cow::Borrowed(s.trim_end())),
+            ValueDeserializer::Value(v) => v.content(),
+            _ => None,
+        }
+    }
+}
+
+impl<'a, 'de> Deserializer<'de> for ValueDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            ValueDeserializer::Value(v) => match v {
+                PropValue::Name(_) | PropValue::Str(_) => visit_cow(v.content().unwrap(), visitor),
+                PropValue::Number(s) => match (v.as_i64(), v.as_f64()) {
+                    (Some(i), _) => visitor.visit_i64(i),
+                    (None, Some(f)) => visitor.visit_f64(f),
+                    (None, None) => visitor.visit_borrowed_str(s),
+                },
+                PropValue::Struct(s) => ValueDeserializer::Struct(s).deserialize_any(visitor),
+                PropValue::Array(a) => visitor.visit_seq(SeqDeserializer(
+                    a.elems
+                        .iter()
+                        .map(ValueDeserializer::Value)
+                        .collect::<Vec<_>>()
+                        .into_iter(),
+                )),
+                // Could be an empty struct or an empty array
+                PropValue::Empty => visitor.visit_seq(SeqDeserializer(vec![].into_iter())),
+            },
+            ValueDeserializer::Struct(s) => visitor.visit_map(StructDeserializer::new(s, &[])),
+            ValueDeserializer::Indexed(elems) => visitor.visit_seq(SeqDeserializer(
+                elems
+                    .into_iter()
+                    .map(ValueDeserializer::Value)
+                    .collect::<Vec<_>>()
+                    .into_iter(),
+            )),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.terminal() {
+            Some(s) if s.eq_ignore_ascii_case("true") => visitor.visit_bool(true),
+            Some(s) if s.eq_ignore_ascii_case("false") => visitor.visit_bool(false),
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self.terminal() {
+            Some(s) => visit_cow(s, visitor),
+            None => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // Missing fields are handled by the derive
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self {
+            ValueDeserializer::Struct(s) | ValueDeserializer::Value(PropValue::Struct(s)) => {
+                visitor.visit_map(StructDeserializer::new(s, fields))
+            }
+            ValueDeserializer::Value(PropValue::Empty) => {
+                static EMPTY: Struct<'static> = Struct { children: vec![] };
+                visitor.visit_map(StructDeserializer::new(&EMPTY, fields))
+            }
+            _ => self.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.terminal() {
+            Some(Cow::Borrowed(s)) => {
+                visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(s))
+            }
+            Some(Cow::Owned(s)) => {
+                visitor.visit_enum(IntoDeserializer::<Error>::into_deserializer(s))
+            }
+            None => Err(de::Error::custom("expected a name for an enum variant")),
+        }
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char bytes byte_buf unit
+        unit_struct seq tuple tuple_struct map identifier
+    }
+}
+
+fn visit_cow<'de, V: Visitor<'de>>(s: Cow<'de, str>, visitor: V) -> Result<V::Value, Error> {
+    match s {
+        Cow::Borrowed(s) => visitor.visit_borrowed_str(s),
+        Cow::Owned(s) => visitor.visit_string(s),
+    }
+}
+
+struct SeqDeserializer<'a, 'de>(std::vec::IntoIter<ValueDeserializer<'a, 'de>>);
+
+impl<'a, 'de> SeqAccess<'de> for SeqDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        self.0.next().map(|v| seed.deserialize(v)).transpose()
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.0.len())
+    }
+}
+
+struct StructDeserializer<'a, 'de> {
+    entries: std::vec::IntoIter<(&'de str, ValueDeserializer<'a, 'de>)>,
+    value: Option<ValueDeserializer<'a, 'de>>,
+}
+
+impl<'a, 'de> StructDeserializer<'a, 'de> {
+    /// Group the children of `s`, using the spelling from `fields` for
+    /// names that match a field.
+    fn new(s: &'a Struct<'de>, fields: &'static [&'static str]) -> Self {
+        let mut entries: Vec<(&'de str, ValueDeserializer<'a, 'de>)> = vec![];
+        let mut indexed: Vec<(usize, Vec<(u64, &'a PropValue<'de>)>)> = vec![];
+        for (name, value) in &s.children {
+            let key = fields
+                .iter()
+                .find(|f| f.eq_ignore_ascii_case(name.name))
+                .copied()
+                .unwrap_or(name.name);
+            match name.idx {
+                None => entries.push((key, ValueDeserializer::Value(value))),
+                Some(idx) => match indexed
+                    .iter_mut()
+                    .find(|(i, _)| entries[*i].0.eq_ignore_ascii_case(key))
+                {
+                    Some((_, elems)) => elems.push((idx, value)),
+                    None => {
+                        indexed.push((entries.len(), vec![(idx, value)]));
+                        entries.push((key, ValueDeserializer::Indexed(vec![])));
+                    }
+                },
+            }
+        }
+        for (i, mut elems) in indexed {
+            elems.sort_by_key(|(idx, _)| *idx);
+            entries[i].1 = ValueDeserializer::Indexed(elems.into_iter().map(|(_, v)| v).collect());
+        }
+
+        Self {
+            entries: entries.into_iter(),
+            value: None,
+        }
+    }
+}
+
+impl<'a, 'de> MapAccess<'de> for StructDeserializer<'a, 'de> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                let key: StrDeserializer<'_, Error> = key.into_deserializer();
+                seed.deserialize(key).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        seed.deserialize(self.value.take().expect("value requested before key"))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.entries.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    use super::from_value;
+    use crate::parse_value;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Cost {
+        item_template_name: String,
+        quantity: u32,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct NewCost {
+        resource_costs: Vec<Cost>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item<'a> {
+        #[serde(rename = "itemname", borrow)]
+        item_name: &'a str,
+        #[serde(rename = "Difficulties")]
+        difficulties: Vec<u8>,
+        #[serde(rename = "NewCost")]
+        new_cost: NewCost,
+        #[serde(rename = "Hidden", default)]
+        hidden: Option<bool>,
+    }
+
+    #[test]
+    fn exciting() {
+        let test_string = r#"(ItemName="EMPGrenadeMk2", Difficulties=(0,1,2), Unknown=1, NewCost=(ResourceCosts[1]=(ItemTemplateName=Intel, Quantity=5), ResourceCosts[0]=(ItemTemplateName="Supplies", Quantity=25)))"#;
+        let item: Item<'_> = from_value(&parse_value(test_string).unwrap()).unwrap();
+        assert_eq!(
+            item,
+            Item {
+                item_name: "EMPGrenadeMk2",
+                difficulties: vec![0, 1, 2],
+                new_cost: NewCost {
+                    resource_costs: vec![
+                        Cost {
+                            item_template_name: "Supplies".to_owned(),
+                            quantity: 25,
+                        },
+                        Cost {
+                            item_template_name: "Intel".to_owned(),
+                            quantity: 5,
+                        },
+                    ],
+                },
+                hidden: None,
+            }
+        );
+
+        let item: Result<Item<'_>, _> =
+            from_value(&parse_value(r#"(ItemName=A, Difficulties=(X), NewCost=())"#).unwrap());
+        assert!(item.is_err());
+    }
+
+    #[test]
+    fn scalars() {
+        let value = crate::parse_value("(True, false, -1.5)").unwrap();
+        let (a, b, c): (bool, bool, f64) = from_value(&value).unwrap();
+        assert_eq!((a, b, c), (true, false, -1.5));
+    }
+}
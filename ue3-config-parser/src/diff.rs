@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+
+use crate::parse::{Directive, Directives, KvpOperation};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ConfigChange<'a> {
+    /// A KVP only present in the new config.
+    Added {
+        section: &'a str,
+        key: &'a str,
+        op: KvpOperation,
+        value: &'a str,
+    },
+    /// A KVP only present in the old config.
+    Removed {
+        section: &'a str,
+        key: &'a str,
+        op: KvpOperation,
+        value: &'a str,
+    },
+    /// A key that is `Set` to different values.
+    Modified {
+        section: &'a str,
+        key: &'a str,
+        old: &'a str,
+        new: &'a str,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Entry<'a> {
+    section: &'a str,
+    key: &'a str,
+    op: KvpOperation,
+    value: &'a str,
+}
+
+impl<'a> Entry<'a> {
+    fn folded_key(&self) -> FoldedKey {
+        (
+            self.section.to_ascii_lowercase(),
+            self.key.to_ascii_lowercase(),
+        )
+    }
+}
+
+fn entries<'a>(directives: &'a Directives<'_>) -> Vec<Entry<'a>> {
+    let mut section = "";
+    let mut entries = vec![];
+    for d in &directives.directives {
+        match d {
+            Directive::SectionHeader(h) => section = &directives.text[h.obj_name],
+            Directive::Kvp(k) => entries.push(Entry {
+                section,
+                key: &directives.text[k.ident],
+                op: k.op,
+                value: directives.text[k.value].trim(),
+            }),
+            Directive::Unknown(_) | Directive::Blank(_) => {}
+        }
+    }
+    entries
+}
+
+type FoldedKey = (String, String);
+
+/// Collect the effective `Set` operations in order of their first appearance.
+/// Later assignments to the same key replace earlier ones.
+fn sets<'a>(entries: &[Entry<'a>]) -> (Vec<FoldedKey>, HashMap<FoldedKey, Entry<'a>>) {
+    let mut order = vec![];
+    let mut map = HashMap::new();
+    for e in entries.iter().filter(|e| e.op == KvpOperation::Set) {
+        let key = e.folded_key();
+        if map.insert(key.clone(), *e).is_none() {
+            order.push(key);
+        }
+    }
+    (order, map)
+}
+
+/// Count the array operations, identified by section, key, operation, and value.
+fn array_ops(entries: &[Entry<'_>]) -> HashMap<(String, String, KvpOperation, String), usize> {
+    let mut counts = HashMap::new();
+    for e in entries.iter().filter(|e| e.op != KvpOperation::Set) {
+        let (section, key) = e.folded_key();
+        *counts
+            .entry((section, key, e.op, e.value.to_owned()))
+            .or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Returns the entries of `entries` that are not matched by an entry in `counts`,
+/// consuming the matched entries from `counts`.
+fn unmatched_array_ops<'a>(
+    entries: &[Entry<'a>],
+    mut counts: HashMap<(String, String, KvpOperation, String), usize>,
+) -> Vec<Entry<'a>> {
+    let mut unmatched = vec![];
+    for e in entries.iter().filter(|e| e.op != KvpOperation::Set) {
+        let (section, key) = e.folded_key();
+        match counts.get_mut(&(section, key, e.op, e.value.to_owned())) {
+            Some(c) if *c > 0 => *c -= 1,
+            _ => unmatched.push(*e),
+        }
+    }
+    unmatched
+}
+
+impl<'a> Directives<'a> {
+    /// Compare the KVPs of `self` (the old config) with `other` (the new config).
+    ///
+    /// KVPs are matched by section and key, ignoring case. `Set` operations are compared
+    /// by their effective (last) value, while array operations are compared as multisets,
+    /// so reordering KVPs doesn't produce any changes.
+    pub fn diff<'s>(&'s self, other: &'s Directives<'_>) -> Vec<ConfigChange<'s>> {
+        let ours = entries(self);
+        let theirs = entries(other);
+        let mut changes = vec![];
+
+        let (our_order, our_sets) = sets(&ours);
+        let (their_order, their_sets) = sets(&theirs);
+        for key in &our_order {
+            let old = &our_sets[key];
+            match their_sets.get(key) {
+                Some(new) if new.value != old.value => changes.push(ConfigChange::Modified {
+                    section: old.section,
+                    key: old.key,
+                    old: old.value,
+                    new: new.value,
+                }),
+                Some(_) => {}
+                None => changes.push(ConfigChange::Removed {
+                    section: old.section,
+                    key: old.key,
+                    op: old.op,
+                    value: old.value,
+                }),
+            }
+        }
+        for key in their_order.iter().filter(|k| !our_sets.contains_key(k)) {
+            let new = &their_sets[key];
+            changes.push(ConfigChange::Added {
+                section: new.section,
+                key: new.key,
+                op: new.op,
+                value: new.value,
+            });
+        }
+
+        for old in unmatched_array_ops(&ours, array_ops(&theirs)) {
+            changes.push(ConfigChange::Removed {
+                section: old.section,
+                key: old.key,
+                op: old.op,
+                value: old.value,
+            });
+        }
+        for new in unmatched_array_ops(&theirs, array_ops(&ours)) {
+            changes.push(ConfigChange::Added {
+                section: new.section,
+                key: new.key,
+                op: new.op,
+                value: new.value,
+            });
+        }
+
+        changes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use crate::parse::Directives;
+
+    #[test]
+    fn reordered() {
+        let old =
+            Directives::from_text("[Engine.Engine]\nA=1\nB=2\n+Arr=X\n+Arr=Y\n[Engine.Other]\nC=3");
+        let new = Directives::from_text(
+            "[engine.other]\n c = 3 \n[Engine.Engine]\n+Arr=Y\nb=2\n+Arr=X\nA=1",
+        );
+        assert!(old.diff(&new).is_empty());
+    }
+
+    #[test]
+    fn modified() {
+        let old = Directives::from_text("[Engine.Engine]\nA=1\nB=2");
+        let new = Directives::from_text("[Engine.Engine]\nA=1\nB=3");
+        let expected = expect![[r#"
+            [
+                Modified {
+                    section: "Engine.Engine",
+                    key: "B",
+                    old: "2",
+                    new: "3",
+                },
+            ]
+        "#]];
+        expected.assert_debug_eq(&old.diff(&new));
+    }
+
+    #[test]
+    fn added() {
+        let old = Directives::from_text("[Engine.Engine]\nA=1\n+Arr=X");
+        let new = Directives::from_text("[Engine.Engine]\nA=1\nB=2\n+Arr=X\n+Arr=X");
+        let expected = expect![[r#"
+            [
+                Added {
+                    section: "Engine.Engine",
+                    key: "B",
+                    op: Set,
+                    value: "2",
+                },
+                Added {
+                    section: "Engine.Engine",
+                    key: "Arr",
+                    op: InsertUnique,
+                    value: "X",
+                },
+            ]
+        "#]];
+        expected.assert_debug_eq(&old.diff(&new));
+    }
+
+    #[test]
+    fn removed() {
+        let old = Directives::from_text("[Engine.Engine]\nA=1\nB=2\n-Arr=X");
+        let new = Directives::from_text("[Engine.Engine]\nB=2");
+        let expected = expect![[r#"
+            [
+                Removed {
+                    section: "Engine.Engine",
+                    key: "A",
+                    op: Set,
+                    value: "1",
+                },
+                Removed {
+                    section: "Engine.Engine",
+                    key: "Arr",
+                    op: Remove,
+                    value: "X",
+                },
+            ]
+        "#]];
+        expected.assert_debug_eq(&old.diff(&new));
+    }
+}
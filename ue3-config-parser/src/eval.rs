@@ -0,0 +1,478 @@
+use std::collections::HashMap;
+
+use crate::{
+    check::struct_syntax::{parse_value, PropValue},
+    check::{ErrorKind, ReportedError, Severity},
+    parse::{Directive, Directives, KvpOperation, Span},
+};
+
+/// Decides whether two array elements are the same for `+` and `-` operations,
+/// see [`element_eq`].
+pub type ElementEq<'e> = &'e dyn Fn(&PropValue<'_>, &PropValue<'_>) -> bool;
+
+/// The comparison [`Directives::evaluate`] uses for `+` and `-` operations:
+/// structural equality as implemented by [`PropValue`]'s `PartialEq`, so
+/// `-Arr=(A=1,B="x")` removes `(A = 1, B = x)`. UE3 itself matches some struct
+/// elements by a few identifying fields only, which
+/// [`Directives::evaluate_with_eq`] can model.
+pub fn element_eq(a: &PropValue<'_>, b: &PropValue<'_>) -> bool {
+    a == b
+}
+
+/// Whether two values are the same element according to `eq`. Values that
+/// can't be parsed are only equal if their text is.
+fn values_eq(a: &str, b: &str, eq: ElementEq<'_>) -> bool {
+    if a == b {
+        return true;
+    }
+    match (parse_value(a), parse_value(b)) {
+        (Ok(a), Ok(b)) => eq(&a, &b),
+        _ => false,
+    }
+}
+
+/// The effective value of a key after applying all operations.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ResolvedValue<'a> {
+    /// The key was only ever `Set`, so the last value wins.
+    Scalar(&'a str),
+    /// The key was modified by array operations.
+    Array(Vec<&'a str>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedSection<'a> {
+    pub name: &'a str,
+    /// The keys in order of their first appearance.
+    pub keys: Vec<(&'a str, ResolvedValue<'a>)>,
+}
+
+/// Like [`ResolvedValue`], but every value comes with the [`Directive::span`] of
+/// the directive that produced it: the last `Set` for a scalar, and the operation
+/// that added each element for an array.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum TracedValue<'a> {
+    Scalar(&'a str, Span),
+    Array(Vec<(&'a str, Span)>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TracedSection<'a> {
+    pub name: &'a str,
+    /// The keys in order of their first appearance.
+    pub keys: Vec<(&'a str, TracedValue<'a>)>,
+}
+
+/// The folded state of a single key.
+struct KeyState<'a> {
+    /// The values and the spans of the directives that added them.
+    values: Vec<(&'a str, Span)>,
+    only_set: bool,
+}
+
+impl<'a> KeyState<'a> {
+    fn new() -> Self {
+        Self {
+            values: vec![],
+            only_set: true,
+        }
+    }
+
+    /// Apply an operation the way UE3 does: `Set` replaces all values, `+` adds a value
+    /// unless it's already present, `.` always adds it, `-` removes every element
+    /// that is equal according to `eq`, and `!` removes all values.
+    ///
+    /// Returns a message if the operation is a likely mistake because it has no effect.
+    fn apply(
+        &mut self,
+        op: KvpOperation,
+        value: &'a str,
+        span: Span,
+        eq: ElementEq<'_>,
+    ) -> Option<&'static str> {
+        let mut no_op = None;
+        match op {
+            KvpOperation::Set => {
+                self.values.clear();
+                self.values.push((value, span));
+            }
+            KvpOperation::InsertUnique => {
+                if self.values.iter().any(|(v, _)| values_eq(v, value, eq)) {
+                    no_op = Some("value is already present, so this insert has no effect");
+                } else {
+                    self.values.push((value, span));
+                }
+            }
+            KvpOperation::Insert => self.values.push((value, span)),
+            KvpOperation::Remove => {
+                let len = self.values.len();
+                self.values.retain(|(v, _)| !values_eq(v, value, eq));
+                if self.values.len() == len {
+                    no_op = Some("value is not present, so this removal has no effect");
+                }
+            }
+            KvpOperation::Clear => self.values.clear(),
+        }
+        self.only_set &= op == KvpOperation::Set;
+        no_op
+    }
+
+    fn resolve(self) -> ResolvedValue<'a> {
+        match (self.only_set, self.values.last()) {
+            (true, Some((v, _))) => ResolvedValue::Scalar(v),
+            _ => ResolvedValue::Array(self.values.into_iter().map(|(v, _)| v).collect()),
+        }
+    }
+
+    fn resolve_traced(self) -> TracedValue<'a> {
+        match (self.only_set, self.values.last()) {
+            (true, Some(&(v, span))) => TracedValue::Scalar(v, span),
+            _ => TracedValue::Array(self.values),
+        }
+    }
+}
+
+/// A section name and the folded state of its keys.
+type FoldedSection<'a> = (&'a str, Vec<(&'a str, KeyState<'a>)>);
+
+fn resolve(sections: Vec<FoldedSection<'_>>) -> Vec<ResolvedSection<'_>> {
+    sections
+        .into_iter()
+        .map(|(name, keys)| ResolvedSection {
+            name,
+            keys: keys.into_iter().map(|(k, s)| (k, s.resolve())).collect(),
+        })
+        .collect()
+}
+
+impl<'a> Directives<'a> {
+    /// Apply all operations and return the effective value of every key.
+    ///
+    /// Sections and keys are matched ignoring case, and repeated sections are merged.
+    /// KVPs before the first section header belong to a section with an empty name.
+    ///
+    /// `+` and `-` operations compare elements with [`element_eq`].
+    pub fn evaluate(&self) -> Vec<ResolvedSection<'a>> {
+        resolve(self.evaluate_into(None, &element_eq))
+    }
+
+    /// Like [`Directives::evaluate`], but `+` and `-` operations treat elements for
+    /// which `eq` returns `true` as the same, e.g. to match structs by an identifying
+    /// field.
+    pub fn evaluate_with_eq(&self, eq: ElementEq<'_>) -> Vec<ResolvedSection<'a>> {
+        resolve(self.evaluate_into(None, eq))
+    }
+
+    /// Like [`Directives::evaluate`], but remembers which directive produced each
+    /// value, e.g. to show where a value was set.
+    pub fn evaluate_traced(&self) -> Vec<TracedSection<'a>> {
+        self.evaluate_into(None, &element_eq)
+            .into_iter()
+            .map(|(name, keys)| TracedSection {
+                name,
+                keys: keys
+                    .into_iter()
+                    .map(|(k, s)| (k, s.resolve_traced()))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// Like [`Directives::evaluate`], but also reports operations that have no effect:
+    /// `-` operations removing a value that isn't present and `+` operations adding a
+    /// value that already is. These are usually mistakes that syntax checks can't catch.
+    pub fn evaluate_with_diagnostics(&self) -> (Vec<ResolvedSection<'a>>, Vec<ReportedError>) {
+        let mut errs = vec![];
+        let sections = resolve(self.evaluate_into(Some(&mut errs), &element_eq));
+        (sections, errs)
+    }
+
+    fn evaluate_into(
+        &self,
+        mut errs: Option<&mut Vec<ReportedError>>,
+        eq: ElementEq<'_>,
+    ) -> Vec<FoldedSection<'a>> {
+        let mut sections: Vec<FoldedSection<'a>> = vec![];
+        let mut section_idx = HashMap::new();
+        let mut key_idx = HashMap::new();
+        let mut current = None;
+
+        for d in &self.directives {
+            match d {
+                Directive::SectionHeader(h) => {
+                    let name = &self.text[h.obj_name];
+                    let idx = *section_idx
+                        .entry(name.to_ascii_lowercase())
+                        .or_insert_with(|| {
+                            sections.push((name, vec![]));
+                            sections.len() - 1
+                        });
+                    current = Some(idx);
+                }
+                Directive::Kvp(k) => {
+                    let section = *current.get_or_insert_with(|| {
+                        *section_idx.entry(String::new()).or_insert_with(|| {
+                            sections.push(("", vec![]));
+                            sections.len() - 1
+                        })
+                    });
+                    let key = &self.text[k.ident];
+                    let keys = &mut sections[section].1;
+                    let idx = *key_idx
+                        .entry((section, key.to_ascii_lowercase()))
+                        .or_insert_with(|| {
+                            keys.push((key, KeyState::new()));
+                            keys.len() - 1
+                        });
+                    let no_op = keys[idx]
+                        .1
+                        .apply(k.op, self.text[k.value].trim(), d.span(), eq);
+                    if let (Some(msg), Some(errs)) = (no_op, errs.as_deref_mut()) {
+                        errs.push(ReportedError {
+                            kind: ErrorKind::Custom {
+                                code: "no-op-operation",
+                                msg: msg.to_owned(),
+                            },
+                            span: d.span(),
+                            severity: Severity::Warning,
+                            related: None,
+                        });
+                    }
+                }
+                Directive::Unknown(_) | Directive::Blank(_) => {}
+            }
+        }
+
+        sections
+    }
+
+    /// Like [`Directives::evaluate`], but only resolves `key` in `section`.
+    /// Returns `None` if the key doesn't appear in the section.
+    pub fn resolved_value(&self, section: &str, key: &str) -> Option<ResolvedValue<'a>> {
+        let mut in_section = section.is_empty();
+        let mut state = None;
+        for d in &self.directives {
+            match d {
+                Directive::SectionHeader(h) => {
+                    in_section = self.text[h.obj_name].eq_ignore_ascii_case(section)
+                }
+                Directive::Kvp(k) if in_section && self.text[k.ident].eq_ignore_ascii_case(key) => {
+                    state.get_or_insert_with(KeyState::new).apply(
+                        k.op,
+                        self.text[k.value].trim(),
+                        d.span(),
+                        &element_eq,
+                    );
+                }
+                _ => {}
+            }
+        }
+        state.map(KeyState::resolve)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::{ResolvedValue, TracedValue};
+    use crate::{parse::Directives, PropValue, Struct};
+
+    const TEXT: &str = "[Engine.GameInfo]
+GameName=First
++Achievements=A
++Achievements=B
+[engine.gameinfo]
+gamename=Second
++Achievements=A
+.Achievements=A
+-Achievements=B
++Achievements=C
+[Engine.Other]
+!Arr=
++Arr=X";
+
+    #[test]
+    fn resolved_value() {
+        let directives = Directives::from_text(TEXT);
+        assert_eq!(
+            directives.resolved_value("Engine.GameInfo", "GameName"),
+            Some(ResolvedValue::Scalar("Second"))
+        );
+        assert_eq!(
+            directives.resolved_value("Engine.GameInfo", "Achievements"),
+            Some(ResolvedValue::Array(vec!["A", "A", "C"]))
+        );
+        assert_eq!(directives.resolved_value("Engine.Other", "GameName"), None);
+    }
+
+    #[test]
+    fn no_op_operations() {
+        let text = "[A.B]\n+Arr=X\n-Arr=Y\n+Arr=X\n.Arr=X\n-Arr=X\n-Arr=X";
+        let (_, errs) = Directives::from_text(text).evaluate_with_diagnostics();
+        let expected = expect![[r#"
+            [
+                ReportedError {
+                    kind: Custom {
+                        code: "no-op-operation",
+                        msg: "value is not present, so this removal has no effect",
+                    },
+                    span: Span(
+                        13,
+                        19,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Custom {
+                        code: "no-op-operation",
+                        msg: "value is already present, so this insert has no effect",
+                    },
+                    span: Span(
+                        20,
+                        26,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+                ReportedError {
+                    kind: Custom {
+                        code: "no-op-operation",
+                        msg: "value is not present, so this removal has no effect",
+                    },
+                    span: Span(
+                        41,
+                        47,
+                    ),
+                    severity: Warning,
+                    related: None,
+                },
+            ]
+        "#]];
+        expected.assert_debug_eq(&errs);
+        let (sections, errs) = Directives::from_text(TEXT).evaluate_with_diagnostics();
+        assert_eq!(sections, Directives::from_text(TEXT).evaluate());
+        // `+Achievements=A` in the repeated section
+        assert_eq!(errs.len(), 1);
+    }
+
+    #[test]
+    fn evaluate() {
+        let expected = expect![[r#"
+            [
+                ResolvedSection {
+                    name: "Engine.GameInfo",
+                    keys: [
+                        (
+                            "GameName",
+                            Scalar(
+                                "Second",
+                            ),
+                        ),
+                        (
+                            "Achievements",
+                            Array(
+                                [
+                                    "A",
+                                    "A",
+                                    "C",
+                                ],
+                            ),
+                        ),
+                    ],
+                },
+                ResolvedSection {
+                    name: "Engine.Other",
+                    keys: [
+                        (
+                            "Arr",
+                            Array(
+                                [
+                                    "X",
+                                ],
+                            ),
+                        ),
+                    ],
+                },
+            ]
+        "#]];
+        expected.assert_debug_eq(&Directives::from_text(TEXT).evaluate());
+    }
+
+    #[test]
+    fn structural_removal() {
+        let text = r#"[A.B]
++Costs=(Name=Supplies, Quantity=25)
++Costs=(Name=Intel, Quantity=10)
++Costs=(Name=Alloys, Quantity=5)
+-Costs=(name = "Supplies",Quantity=25)
+-Costs=(Name=Intel, Quantity=11)"#;
+        let directives = Directives::from_text(text);
+        assert_eq!(
+            directives.resolved_value("A.B", "Costs"),
+            Some(ResolvedValue::Array(vec![
+                "(Name=Intel, Quantity=10)",
+                "(Name=Alloys, Quantity=5)"
+            ]))
+        );
+        let (_, errs) = directives.evaluate_with_diagnostics();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(&text[errs[0].span], "-Costs=(Name=Intel, Quantity=11)");
+
+        // Match costs by their name only
+        fn name(s: &Struct<'_>) -> Option<String> {
+            s.children
+                .iter()
+                .find(|(n, _)| n.name.trim_end().eq_ignore_ascii_case("Name"))
+                .and_then(|(_, v)| v.content())
+                .map(|c| c.into_owned())
+        }
+        let by_name = |a: &PropValue<'_>, b: &PropValue<'_>| match (a, b) {
+            (PropValue::Struct(a), PropValue::Struct(b)) => name(a).is_some() && name(a) == name(b),
+            _ => a == b,
+        };
+        let sections = directives.evaluate_with_eq(&by_name);
+        assert_eq!(
+            sections[0].keys[0].1,
+            ResolvedValue::Array(vec!["(Name=Alloys, Quantity=5)"])
+        );
+    }
+
+    #[test]
+    fn structural_insert_unique() {
+        let text =
+            "[A.B]\n+Costs=(Name=Supplies, Quantity=25)\n+Costs=(name = \"Supplies\",Quantity=25)";
+        let directives = Directives::from_text(text);
+        assert_eq!(
+            directives.resolved_value("A.B", "Costs"),
+            Some(ResolvedValue::Array(vec!["(Name=Supplies, Quantity=25)"]))
+        );
+        let (_, errs) = directives.evaluate_with_diagnostics();
+        assert_eq!(errs.len(), 1);
+        assert_eq!(errs[0].kind.code(), "no-op-operation");
+    }
+
+    #[test]
+    fn evaluate_traced() {
+        let text = "[A.B]\nKey=1\nKey=2\nKey=3\n+Arr=X\n.Arr=Y\n-Arr=X\n+Arr=Z";
+        let directives = Directives::from_text(text);
+        let sections = directives.evaluate_traced();
+        assert_eq!(sections.len(), 1);
+        let keys = &sections[0].keys;
+
+        let (value, span) = match &keys[0] {
+            ("Key", TracedValue::Scalar(value, span)) => (*value, *span),
+            other => panic!("unexpected key {:?}", other),
+        };
+        assert_eq!(value, "3");
+        assert_eq!(&text[span], "Key=3");
+
+        let elements = match &keys[1] {
+            ("Arr", TracedValue::Array(elements)) => elements,
+            other => panic!("unexpected key {:?}", other),
+        };
+        let lines: Vec<_> = elements.iter().map(|&(v, span)| (v, &text[span])).collect();
+        assert_eq!(lines, [("Y", ".Arr=Y"), ("Z", "+Arr=Z")]);
+    }
+}
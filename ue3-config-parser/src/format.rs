@@ -0,0 +1,404 @@
+use crate::parse::{split_lines, Directive, Directives, Span};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    /// `CrLf` on Windows, `Lf` everywhere else.
+    Native,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Native if cfg!(windows) => "\r\n",
+            LineEnding::Native => "\n",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct FormatOptions {
+    /// The number of spaces continuation lines are indented with.
+    pub indent_width: usize,
+    /// The number of blank lines before every section header but the first.
+    pub blank_lines_between_sections: usize,
+    /// The line ending used for every line, regardless of the line endings in the input.
+    pub line_ending: LineEnding,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            indent_width: 4,
+            blank_lines_between_sections: 1,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+impl<'a> Directives<'a> {
+    /// Rebuild the text with a canonical layout:
+    ///
+    /// * Section headers, keys, and comments start at the beginning of the line.
+    /// * There is no whitespace around the `=` of a KVP.
+    /// * Continuation lines are indented by `indent_width` spaces.
+    /// * Sections are separated by `blank_lines_between_sections` blank lines, and
+    ///   comments directly above a header stay attached to it.
+    /// * Runs of [`Directive::Blank`] lines within a section are collapsed to one.
+    ///
+    /// Operations, keys, values, and comments are otherwise preserved as written.
+    pub fn format(&self, opts: FormatOptions) -> String {
+        let indent = " ".repeat(opts.indent_width);
+        let newline = opts.line_ending.as_str();
+        let mut out = String::with_capacity(self.text.len());
+        let mut pending_blank = false;
+
+        for (i, d) in self.directives.iter().enumerate() {
+            if let Directive::Blank(_) = d {
+                pending_blank = true;
+                continue;
+            }
+            if !out.is_empty() {
+                if self.starts_section(i) {
+                    for _ in 0..opts.blank_lines_between_sections {
+                        out.push_str(newline);
+                    }
+                } else if pending_blank {
+                    out.push_str(newline);
+                }
+            }
+            pending_blank = false;
+            self.write_directive(&mut out, d, &indent, newline);
+        }
+
+        out
+    }
+
+    /// Rebuild the text like [`Directives::format`] with default options, but with
+    /// sections sorted by name and keys sorted within each section, ignoring case.
+    ///
+    /// Both sorts are stable, and all operations on a key move together, so the
+    /// relative order of `+`/`-`/`.`/`!` sequences (and thus the evaluated config)
+    /// is unchanged. KVPs before the first section header stay at the top.
+    /// Comments move with the directive that follows them, and comments at the
+    /// end of the text stay at the end.
+    pub fn canonicalize(&self) -> String {
+        struct Section<'d> {
+            name: String,
+            /// The header and the comments above it.
+            header: Vec<&'d Directive>,
+            keys: Vec<(String, Vec<&'d Directive>)>,
+        }
+
+        let mut sections = vec![Section {
+            name: String::new(),
+            header: vec![],
+            keys: vec![],
+        }];
+        let mut pending = vec![];
+        for d in &self.directives {
+            match d {
+                Directive::SectionHeader(h) => {
+                    pending.push(d);
+                    sections.push(Section {
+                        name: self.header_text(h).to_ascii_lowercase(),
+                        header: std::mem::take(&mut pending),
+                        keys: vec![],
+                    });
+                }
+                Directive::Kvp(k) if self.is_commented_out(k) => pending.push(d),
+                Directive::Kvp(k) => {
+                    pending.push(d);
+                    let key = self.key_text(k).to_ascii_lowercase();
+                    let section = sections.last_mut().unwrap();
+                    section.keys.push((key, std::mem::take(&mut pending)));
+                }
+                Directive::Unknown(_) => pending.push(d),
+                Directive::Blank(_) => {}
+            }
+        }
+
+        sections[1..].sort_by(|a, b| a.name.cmp(&b.name));
+        let indent = " ".repeat(FormatOptions::default().indent_width);
+        let mut out = String::with_capacity(self.text.len());
+        for mut section in sections {
+            if section.header.is_empty() && section.keys.is_empty() {
+                continue;
+            }
+            if !out.is_empty() {
+                out.push('\n');
+            }
+            section.keys.sort_by(|a, b| a.0.cmp(&b.0));
+            let keys = section.keys.into_iter().flat_map(|(_, ds)| ds);
+            for d in section.header.into_iter().chain(keys) {
+                self.write_directive(&mut out, d, &indent, "\n");
+            }
+        }
+        for d in pending {
+            self.write_directive(&mut out, d, &indent, "\n");
+        }
+
+        out
+    }
+
+    fn write_directive(&self, out: &mut String, d: &Directive, indent: &str, newline: &str) {
+        match d {
+            Directive::SectionHeader(h) => {
+                out.push_str(&self.text[h.trimmed]);
+                if let Some(comment) = h.comment {
+                    out.push(' ');
+                    out.push_str(self.text[comment].trim_end_matches([' ', '\t']));
+                }
+            }
+            Directive::Kvp(k) if self.is_commented_out(k) => {
+                out.push_str(self.text[d.span()].trim_end_matches([' ', '\t']));
+            }
+            Directive::Kvp(k) => {
+                let op = &self.text[Span(d.span().0, k.span.0)];
+                out.push_str(op);
+                out.push_str(&self.text[k.ident]);
+                out.push('=');
+                let value = &self.text[k.value];
+                let mut prev: Option<Span> = None;
+                for line in split_lines(value) {
+                    match prev {
+                        // Line breaks in quoted strings are part of the value, but
+                        // still use the configured line ending
+                        Some(p) if !value[p].ends_with(r"\\") => {
+                            let breaks = &value[Span(p.1, line.0)];
+                            let count = breaks.matches(['\r', '\n']).count()
+                                - breaks.matches("\r\n").count();
+                            for _ in 0..count {
+                                out.push_str(newline);
+                            }
+                            out.push_str(&value[line]);
+                        }
+                        Some(_) => {
+                            out.push_str(newline);
+                            out.push_str(indent);
+                            out.push_str(value[line].trim_start_matches([' ', '\t']));
+                        }
+                        None => out.push_str(value[line].trim_start_matches([' ', '\t'])),
+                    }
+                    prev = Some(line);
+                }
+                if let Some(comment) = k.comment {
+                    out.push(' ');
+                    out.push_str(self.text[comment].trim_end_matches([' ', '\t']));
+                }
+            }
+            Directive::Unknown(u) => {
+                out.push_str(self.text[u.span].trim_matches([' ', '\t']));
+            }
+            Directive::Blank(_) => {}
+        }
+        out.push_str(newline);
+    }
+
+    /// Whether the section separator goes before the directive at `index`, i.e.
+    /// it is a section header or the first of the comments directly above one.
+    fn starts_section(&self, index: usize) -> bool {
+        if index > 0 && self.is_comment(&self.directives[index - 1]) {
+            return false;
+        }
+        self.directives[index..]
+            .iter()
+            .find(|d| !self.is_comment(d))
+            .is_some_and(|d| matches!(d, Directive::SectionHeader(_)))
+    }
+
+    fn is_comment(&self, d: &Directive) -> bool {
+        match d {
+            Directive::Unknown(u) => self.text[u.span].trim_start().starts_with(';'),
+            Directive::Kvp(k) => self.is_commented_out(k),
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::{FormatOptions, LineEnding};
+    use crate::parse::{Directives, ParseOptions};
+
+    const MESSY: &str = "  ; Engine settings
+[Engine.Engine]
+  Key = Value
++Arr  =  (A=1, \\\\
+B=2, \\\\
+        C=3)
+; Other settings
+; more
+[Engine.Other]   ; note
+!  Arr=
+
+
++ Arr=X
+";
+
+    #[test]
+    fn format() {
+        let expected = expect![[r#"
+            ; Engine settings
+            [Engine.Engine]
+            Key=Value
+            +Arr=(A=1, \\
+                B=2, \\
+                C=3)
+
+            ; Other settings
+            ; more
+            [Engine.Other] ; note
+            !Arr=
+            +Arr=X
+        "#]];
+        let formatted = Directives::from_text(MESSY).format(FormatOptions::default());
+        expected.assert_eq(&formatted);
+
+        let expected = expect![[r#"
+            ; Engine settings
+            [Engine.Engine]
+            Key=Value
+            +Arr=(A=1, \\
+              B=2, \\
+              C=3)
+
+
+            ; Other settings
+            ; more
+            [Engine.Other] ; note
+            !Arr=
+
+            +Arr=X
+        "#]];
+        let options = ParseOptions {
+            keep_blank_lines: true,
+            ..Default::default()
+        };
+        let opts = FormatOptions {
+            indent_width: 2,
+            blank_lines_between_sections: 2,
+            ..Default::default()
+        };
+        expected.assert_eq(&Directives::from_text_with_options(MESSY, &options).format(opts));
+    }
+
+    #[test]
+    fn idempotent() {
+        for &keep_blank_lines in &[false, true] {
+            let options = ParseOptions {
+                keep_blank_lines,
+                ..Default::default()
+            };
+            let once = Directives::from_text_with_options(MESSY, &options)
+                .format(FormatOptions::default());
+            let twice = Directives::from_text_with_options(&once, &options)
+                .format(FormatOptions::default());
+            assert_eq!(once, twice);
+        }
+    }
+
+    #[test]
+    fn canonicalize() {
+        let text = "Top=1
+[Engine.Zeta]
+; zeta comment
+; set   zkey = 0 to disable
+zkey=1
++Arr=B
+Akey=2
+-Arr=A
+[Engine.Alpha]
++Arr=X
+
+; about MiddleKey
+MiddleKey=(A=1, \\\\
+  B=2)
+.Arr=Y
+!Arr=
++Arr=Z
+; above beta
+[engine.beta]
+Key=Value
+; end of beta
+";
+        let expected = expect![[r#"
+            Top=1
+
+            [Engine.Alpha]
+            +Arr=X
+            .Arr=Y
+            !Arr=
+            +Arr=Z
+            ; about MiddleKey
+            MiddleKey=(A=1, \\
+                B=2)
+
+            ; above beta
+            [engine.beta]
+            Key=Value
+
+            [Engine.Zeta]
+            Akey=2
+            +Arr=B
+            -Arr=A
+            ; zeta comment
+            ; set   zkey = 0 to disable
+            zkey=1
+            ; end of beta
+        "#]];
+        let directives = Directives::from_text(text);
+        let canonical = directives.canonicalize();
+        expected.assert_eq(&canonical);
+
+        let reparsed = Directives::from_text(&canonical);
+        for (section, key) in [("Engine.Alpha", "Arr"), ("Engine.Zeta", "Arr")] {
+            assert_eq!(
+                reparsed.resolved_value(section, key),
+                directives.resolved_value(section, key)
+            );
+        }
+        assert_eq!(reparsed.canonicalize(), canonical);
+    }
+
+    #[test]
+    fn multiline_strings() {
+        let text = "[A.B]\n  Text = \"First line\n\n   indented\" \\\\\n  , \"x\"\nKey=1\n";
+        let expected = expect![[r#"
+            [A.B]
+            Text="First line
+
+               indented" \\
+                , "x"
+            Key=1
+        "#]];
+        expected.assert_eq(&Directives::from_text(text).format(FormatOptions::default()));
+    }
+
+    #[test]
+    fn line_endings() {
+        let text = "[A.B]\r\nKey=1\n+Arr=(A=1, \\\\\r\nB=2)\r[A.C]\nKey=2\r\n";
+        let directives = Directives::from_text(text);
+
+        let lf = directives.format(FormatOptions::default());
+        assert!(!lf.contains('\r'));
+        assert_eq!(lf.lines().count(), 7);
+
+        let opts = FormatOptions {
+            line_ending: LineEnding::CrLf,
+            ..Default::default()
+        };
+        let crlf = directives.format(opts);
+        assert_eq!(crlf, lf.replace('\n', "\r\n"));
+
+        // Including line breaks in quoted strings
+        let text = "[A.B]\r\nText=\"a\r\n\r\nb\"\r\n";
+        let lf = Directives::from_text(text).format(FormatOptions::default());
+        assert_eq!(lf, "[A.B]\nText=\"a\n\nb\"\n");
+    }
+}
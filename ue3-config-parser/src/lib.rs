@@ -1,2 +1,16 @@
+pub mod build;
 pub mod check;
+#[cfg(feature = "serde")]
+pub mod de;
+pub mod diff;
+pub mod eval;
+pub mod format;
+pub mod outline;
 pub mod parse;
+#[cfg(feature = "serde")]
+pub mod ser;
+
+pub use check::struct_syntax::{
+    parse_value, Array, ExpectedToken, IndexStyle, ParseError, PropKind, PropName, PropValue,
+    Struct,
+};
@@ -0,0 +1,377 @@
+use std::collections::{BTreeSet, HashSet};
+
+use crate::parse::{Directive, Directives, LineIndex, Span};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FoldKind {
+    Section,
+    Value,
+}
+
+/// A foldable range of lines. Line numbers are 1-based, as in [`LineIndex`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FoldRange {
+    pub start_line: u32,
+    pub end_line: u32,
+    pub kind: FoldKind,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Namespace,
+    Field,
+}
+
+/// A node of the document outline. Sections are `Namespace` symbols with their
+/// KVPs as `Field` children.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol<'a> {
+    pub name: &'a str,
+    pub kind: SymbolKind,
+    pub span: Span,
+    pub children: Vec<Symbol<'a>>,
+}
+
+/// What can be written at a given position, see [`Directives::context_at`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CompletionContext<'a> {
+    /// Inside the brackets of a section header.
+    SectionName,
+    /// At the start of a line, where an operation, a key, or a section header can be written.
+    Operation,
+    /// Inside a key. `section` is `None` before the first section header.
+    Key { section: Option<&'a str> },
+    /// After the `=` of a KVP.
+    Value {
+        section: Option<&'a str>,
+        key: &'a str,
+    },
+    /// Inside a comment or otherwise unrecognized line.
+    None,
+}
+
+impl<'a> Directives<'a> {
+    /// Returns the folding ranges of every section and every multiline KVP, ordered by
+    /// their starting line.
+    ///
+    /// A section spans from its header to its last directive, so blank lines
+    /// before the next header are not folded.
+    pub fn folding_ranges(&self) -> Vec<FoldRange> {
+        let index = LineIndex::new(self.text);
+        let mut ranges = vec![];
+        let mut section: Option<FoldRange> = None;
+
+        for d in &self.directives {
+            let span = d.span();
+            match d {
+                Directive::SectionHeader(_) => {
+                    ranges.extend(section.filter(|s| s.end_line > s.start_line));
+                    let line = index.line(span.0);
+                    section = Some(FoldRange {
+                        start_line: line,
+                        end_line: line,
+                        kind: FoldKind::Section,
+                    });
+                    continue;
+                }
+                Directive::Kvp(_) => {
+                    let (start_line, end_line) = (index.line(span.0), index.line(span.1));
+                    if end_line > start_line {
+                        ranges.push(FoldRange {
+                            start_line,
+                            end_line,
+                            kind: FoldKind::Value,
+                        });
+                    }
+                }
+                Directive::Unknown(_) => {}
+                Directive::Blank(_) => continue,
+            }
+            if let Some(section) = &mut section {
+                section.end_line = index.line(span.1);
+            }
+        }
+        ranges.extend(section.filter(|s| s.end_line > s.start_line));
+
+        ranges.sort_by_key(|r| r.start_line);
+        ranges
+    }
+
+    /// Determines what is expected at byte offset `byte`, e.g. for completion.
+    pub fn context_at(&self, byte: usize) -> CompletionContext<'a> {
+        let mut section = None;
+        for d in &self.directives {
+            let span = d.span();
+            if span.0 > byte {
+                break;
+            }
+            if let Directive::SectionHeader(h) = d {
+                section = Some(&self.text[h.obj_name]);
+            }
+            if byte > span.1 {
+                continue;
+            }
+
+            return match d {
+                Directive::SectionHeader(_) => CompletionContext::SectionName,
+                Directive::Kvp(k) => {
+                    if byte == span.0 {
+                        CompletionContext::Operation
+                    } else if byte < k.value.0 {
+                        CompletionContext::Key { section }
+                    } else {
+                        CompletionContext::Value {
+                            section,
+                            key: &self.text[k.ident],
+                        }
+                    }
+                }
+                Directive::Unknown(u) => {
+                    let line = &self.text[u.span];
+                    let trimmed = line.trim_start_matches([' ', '\t']);
+                    let start = u.span.0 + line.len() - trimmed.len();
+                    if trimmed.starts_with(';') || trimmed.starts_with("//") {
+                        CompletionContext::None
+                    } else if trimmed.starts_with('[') {
+                        CompletionContext::SectionName
+                    } else if byte <= start {
+                        CompletionContext::Operation
+                    } else {
+                        CompletionContext::Key { section }
+                    }
+                }
+                Directive::Blank(_) => CompletionContext::Operation,
+            };
+        }
+        CompletionContext::Operation
+    }
+
+    /// Returns the distinct keys of all KVPs, without any `[n]` or `(n)` index.
+    ///
+    /// If `ignore_case` is set, keys that only differ by case are reported once,
+    /// spelled as they first appear.
+    pub fn unique_keys(&self, ignore_case: bool) -> BTreeSet<&'a str> {
+        let mut keys = BTreeSet::new();
+        let mut seen = HashSet::new();
+        for d in &self.directives {
+            if let Directive::Kvp(k) = d {
+                if self.is_commented_out(k) {
+                    continue;
+                }
+                let key = &self.text[k.ident];
+                let base = key.find(['[', '(']).map_or(key, |p| &key[..p]);
+                if !ignore_case || seen.insert(base.to_ascii_lowercase()) {
+                    keys.insert(base);
+                }
+            }
+        }
+        keys
+    }
+
+    /// Returns the outline of the document.
+    ///
+    /// KVPs before the first section header are collected in a section symbol with
+    /// an empty name. A section's span extends to the end of its last KVP.
+    /// Commented-out KVPs are skipped.
+    pub fn document_symbols(&self) -> Vec<Symbol<'a>> {
+        let mut symbols: Vec<Symbol<'a>> = vec![];
+        for d in &self.directives {
+            match d {
+                Directive::SectionHeader(h) => symbols.push(Symbol {
+                    name: &self.text[h.obj_name],
+                    kind: SymbolKind::Namespace,
+                    span: h.span,
+                    children: vec![],
+                }),
+                Directive::Kvp(k) if self.is_commented_out(k) => {}
+                Directive::Kvp(k) => {
+                    let span = d.span();
+                    if symbols.is_empty() {
+                        symbols.push(Symbol {
+                            name: "",
+                            kind: SymbolKind::Namespace,
+                            span: Span(span.0, span.0),
+                            children: vec![],
+                        });
+                    }
+                    let section = symbols.last_mut().unwrap();
+                    section.span.1 = span.1;
+                    section.children.push(Symbol {
+                        name: &self.text[k.ident],
+                        kind: SymbolKind::Field,
+                        span,
+                        children: vec![],
+                    });
+                }
+                Directive::Unknown(_) | Directive::Blank(_) => {}
+            }
+        }
+        symbols
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use expect_test::expect;
+
+    use super::CompletionContext;
+    use crate::parse::Directives;
+
+    #[test]
+    fn folding_ranges() {
+        let text =
+            "[A.B]\nKey=1\n+Arr=(A=1, \\\\\n  B=2, \\\\\n  C=3)\n\n[A.C]\nKey=2\n; Comment\n";
+        let expected = expect![[r#"
+            [
+                FoldRange {
+                    start_line: 1,
+                    end_line: 5,
+                    kind: Section,
+                },
+                FoldRange {
+                    start_line: 3,
+                    end_line: 5,
+                    kind: Value,
+                },
+                FoldRange {
+                    start_line: 7,
+                    end_line: 9,
+                    kind: Section,
+                },
+            ]
+        "#]];
+        expected.assert_debug_eq(&Directives::from_text(text).folding_ranges());
+    }
+
+    #[test]
+    fn document_symbols() {
+        let text = "Orphan=0\n[A.B]\nKey=1\n+Arr=X\n; Comment\n[A.C]\nKey=2\n; set Foo=1 to enable";
+        let expected = expect![[r#"
+            [
+                Symbol {
+                    name: "",
+                    kind: Namespace,
+                    span: Span(
+                        0,
+                        8,
+                    ),
+                    children: [
+                        Symbol {
+                            name: "Orphan",
+                            kind: Field,
+                            span: Span(
+                                0,
+                                8,
+                            ),
+                            children: [],
+                        },
+                    ],
+                },
+                Symbol {
+                    name: "A.B",
+                    kind: Namespace,
+                    span: Span(
+                        9,
+                        27,
+                    ),
+                    children: [
+                        Symbol {
+                            name: "Key",
+                            kind: Field,
+                            span: Span(
+                                15,
+                                20,
+                            ),
+                            children: [],
+                        },
+                        Symbol {
+                            name: "Arr",
+                            kind: Field,
+                            span: Span(
+                                21,
+                                27,
+                            ),
+                            children: [],
+                        },
+                    ],
+                },
+                Symbol {
+                    name: "A.C",
+                    kind: Namespace,
+                    span: Span(
+                        38,
+                        49,
+                    ),
+                    children: [
+                        Symbol {
+                            name: "Key",
+                            kind: Field,
+                            span: Span(
+                                44,
+                                49,
+                            ),
+                            children: [],
+                        },
+                    ],
+                },
+            ]
+        "#]];
+        expected.assert_debug_eq(&Directives::from_text(text).document_symbols());
+    }
+
+    #[test]
+    fn context_at() {
+        let text = "[A.B]\nKey=1\n+Arr=X\n; Comment\n  Partial\n\n";
+        let directives = Directives::from_text(text);
+        let section = Some("A.B");
+        let cases = [
+            (2, CompletionContext::SectionName),
+            (6, CompletionContext::Operation),
+            (8, CompletionContext::Key { section }),
+            (
+                10,
+                CompletionContext::Value {
+                    section,
+                    key: "Key",
+                },
+            ),
+            (12, CompletionContext::Operation),
+            (13, CompletionContext::Key { section }),
+            (
+                17,
+                CompletionContext::Value {
+                    section,
+                    key: "Arr",
+                },
+            ),
+            (22, CompletionContext::None),
+            (31, CompletionContext::Operation),
+            (35, CompletionContext::Key { section }),
+            (text.len(), CompletionContext::Operation),
+        ];
+        for (byte, expected) in &cases {
+            assert_eq!(directives.context_at(*byte), *expected, "at {}", byte);
+        }
+        assert_eq!(
+            Directives::from_text("Key=").context_at(2),
+            CompletionContext::Key { section: None }
+        );
+    }
+
+    #[test]
+    fn unique_keys() {
+        let text = "[A.B]\nFoo[0]=1\nFoo[1]=2\nBar=3\n[A.C]\n+foo(2)=4\n; Baz=5";
+        let directives = Directives::from_text(text);
+        assert_eq!(
+            directives
+                .unique_keys(false)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["Bar", "Foo", "foo"]
+        );
+        assert_eq!(
+            directives.unique_keys(true).into_iter().collect::<Vec<_>>(),
+            ["Bar", "Foo"]
+        );
+    }
+}
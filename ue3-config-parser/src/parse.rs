@@ -1,20 +1,32 @@
-use std::ops::Index;
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    fmt::Display,
+    ops::{Index, Range},
+};
 
-#[derive(Clone, Copy, Debug)]
+use memchr::memchr2;
+
+/// A byte range in the text. Spans are ordered by start, then by end.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct Span(pub usize, pub usize);
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Identifier {
     pub span: Span,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct SectionHeader {
     pub span: Span,
+    /// The `[...]` part of the line, without surrounding whitespace.
+    pub trimmed: Span,
     pub obj_name: Span,
+    /// A trailing `;` comment after the `]`, up to the end of the line.
+    pub comment: Option<Span>,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum KvpOperation {
     Set,
     Insert,
@@ -35,24 +47,85 @@ impl From<u8> for KvpOperation {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Kvp {
     pub span: Span,
+    /// The key, which never includes the operation character.
     pub ident: Span,
     pub value: Span,
     pub op: KvpOperation,
+    /// A trailing `; comment` after the value, only with
+    /// [`ParseOptions::inline_comments`]. It is part of `span`, but not of `value`.
+    pub comment: Option<Span>,
+    /// The number of lines the KVP occupies, i.e. one more than the number
+    /// of `\\` continuations and line breaks in quoted strings. Blank lines
+    /// are not counted.
+    pub line_count: u32,
+}
+
+impl Kvp {
+    /// The value with its `\\` continuations joined into a single line, see
+    /// [`join_continuations`]. Line breaks in quoted strings are kept. `text` is
+    /// the text the KVP was parsed from. Only allocates for multiline values.
+    pub fn logical_value<'t>(&self, text: &'t str) -> Cow<'t, str> {
+        join_continuations(&text[self.value]).0
+    }
 }
-#[derive(Clone, Copy, Debug)]
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Unknown {
     pub span: Span,
     pub prev_span: Option<Span>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Directive {
     SectionHeader(SectionHeader),
     Kvp(Kvp),
     Unknown(Unknown),
+    /// An empty or whitespace-only line. Only produced with
+    /// [`ParseOptions::keep_blank_lines`].
+    Blank(Span),
+}
+
+impl Directive {
+    /// The span of the whole directive. This includes the operation of a KVP,
+    /// all continuation lines of a multiline value, and a [`Kvp::comment`].
+    pub fn span(&self) -> Span {
+        match self {
+            Directive::SectionHeader(h) => h.span,
+            Directive::Kvp(k) if k.op != KvpOperation::Set => Span(k.span.0 - 1, k.span.1),
+            Directive::Kvp(k) => k.span,
+            Directive::Unknown(u) => u.span,
+            Directive::Blank(span) => *span,
+        }
+    }
+}
+
+/// Options for [`Directives::from_text_with_options`]. The [`Default`] options parse the
+/// same way [`Directives::from_text`] does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    /// Emit a [`Directive::Blank`] for every blank line instead of skipping them.
+    /// Defaults to `false`.
+    pub keep_blank_lines: bool,
+    /// End a value at a `;` outside of quotes and parentheses on its last line,
+    /// and record the rest of the line as [`Kvp::comment`]. Otherwise, such a
+    /// comment is part of the value. Defaults to `false`.
+    pub inline_comments: bool,
+}
+
+/// The number of directives of each kind, see [`Directives::stats`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DirectiveStats {
+    pub sections: usize,
+    pub kvps: usize,
+    /// [`Directive::Unknown`] lines that are `;` comments.
+    pub comments: usize,
+    /// All other [`Directive::Unknown`] lines.
+    pub unknowns: usize,
+    /// KVPs that span more than one line. These are also counted in `kvps`.
+    pub multiline_kvps: usize,
 }
 
 #[derive(Clone, Debug)]
@@ -61,6 +134,25 @@ pub struct Directives<'a> {
     pub directives: Vec<Directive>,
 }
 
+/// [`Directives`] that own their text, see [`Directives::into_owned`] and
+/// [`Directives::from_bytes_lossy`]. All spans are offsets into `text`, which for
+/// the latter is the decoded text and not the original bytes.
+#[derive(Clone, Debug)]
+pub struct OwnedDirectives {
+    pub text: String,
+    pub directives: Vec<Directive>,
+}
+
+impl OwnedDirectives {
+    /// A [`Directives`] view of the text. The directives are copied, the text is borrowed.
+    pub fn as_directives(&self) -> Directives<'_> {
+        Directives {
+            text: &self.text,
+            directives: self.directives.clone(),
+        }
+    }
+}
+
 impl Index<Span> for str {
     type Output = str;
 
@@ -79,48 +171,272 @@ impl Index<&Span> for str {
     }
 }
 
-impl<'a> Directives<'a> {
-    pub fn from_text(text: &'a str) -> Self {
-        // Split our input text into lines
-        let lines = {
-            let mut lines = vec![];
-            let mut remaining = text;
-            let mut offset = 0;
-            while !remaining.is_empty() {
-                match remaining.find(|c| matches!(c, '\r' | '\n')) {
-                    Some(p) => {
-                        lines.push(Span(offset, offset + p));
-                        offset += p;
-                        remaining = &remaining[p..];
-                        while remaining.starts_with(|c| matches!(c, '\r' | '\n')) {
-                            offset += 1;
-                            remaining = &remaining[1..];
-                        }
-                    }
-                    None => {
-                        lines.push(Span(offset, offset + remaining.len()));
-                        break;
+/// Maps byte offsets in a text to 1-based line and column numbers.
+///
+/// `\r\n`, `\r`, and `\n` are all accepted as line breaks. Columns are counted in
+/// characters, not bytes.
+#[derive(Clone, Debug)]
+pub struct LineIndex<'a> {
+    text: &'a str,
+    /// The byte offset of the start of every line.
+    starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(text: &'a str) -> Self {
+        let bytes = text.as_bytes();
+        let mut starts = vec![0];
+        for (i, b) in bytes.iter().enumerate() {
+            match b {
+                b'\n' => starts.push(i + 1),
+                b'\r' if bytes.get(i + 1) != Some(&b'\n') => starts.push(i + 1),
+                _ => {}
+            }
+        }
+        Self { text, starts }
+    }
+
+    /// The 1-based line of byte offset `pos`.
+    pub fn line(&self, pos: usize) -> u32 {
+        self.starts.partition_point(|&s| s <= pos) as u32
+    }
+
+    /// The 1-based line and column of byte offset `pos`.
+    pub fn line_col(&self, pos: usize) -> (u32, u32) {
+        let line = self.line(pos);
+        let start = self.starts[line as usize - 1];
+        let col = self.text[start..pos].chars().count() as u32 + 1;
+        (line, col)
+    }
+}
+
+/// Whether a quoted string is still open at the end of `line`, given whether
+/// one was open at its start. Inside quotes, `\` escapes the next character.
+///
+/// A string only opens where a value starts, i.e. at the start of `line` or
+/// after a `=`, `(`, or `,`. Other quotes, like the inch mark in `5" monitor`,
+/// are part of an unquoted value.
+fn ends_quoted(line: &str, mut quoted: bool) -> bool {
+    // The last character outside of quotes that isn't whitespace
+    let mut prev = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted => {
+                quoted = false;
+                prev = Some(c);
+            }
+            '\\' if quoted => {
+                chars.next();
+            }
+            _ if quoted => {}
+            '"' if matches!(prev, None | Some('=' | '(' | ',')) => quoted = true,
+            ' ' | '\t' => {}
+            _ => prev = Some(c),
+        }
+    }
+    quoted
+}
+
+/// Whether `line` starts like a KVP, with an optional operation, a key, and a `=`.
+/// A multiline string never continues onto such a line.
+fn starts_kvp(line: &str) -> bool {
+    let line = line.trim_start_matches([' ', '\t']);
+    let line = line
+        .strip_prefix(['+', '-', '.', '!'])
+        .unwrap_or(line)
+        .trim_start_matches([' ', '\t']);
+    let key_len = line
+        .find(|c: char| !(c.is_ascii_alphanumeric() || matches!(c, '_' | '[' | ']' | '(' | ')')))
+        .unwrap_or(line.len());
+    line.starts_with(|c: char| c.is_ascii_alphabetic())
+        && line[key_len..]
+            .trim_start_matches([' ', '\t'])
+            .starts_with('=')
+}
+
+/// Trim the line at `span` for parsing it as a section header. Returns the span of
+/// the header without surrounding whitespace, and of a trailing `;` comment.
+fn trim_header(text: &str, span: Span) -> (Span, Option<Span>) {
+    let mut header_span = span;
+    while let Some(b' ' | b'\t') = text[header_span].as_bytes().first() {
+        header_span.0 += 1;
+    }
+    // A `;` outside of the brackets starts a trailing comment
+    let mut comment = None;
+    let mut depth = 0usize;
+    for (i, b) in text[header_span].bytes().enumerate() {
+        match b {
+            b'[' => depth += 1,
+            b']' => depth = depth.saturating_sub(1),
+            b';' if depth == 0 => {
+                comment = Some(Span(header_span.0 + i, header_span.1));
+                header_span.1 = header_span.0 + i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    while let Some(b' ' | b'\t') = text[header_span].as_bytes().last() {
+        header_span.1 -= 1;
+    }
+    (header_span, comment)
+}
+
+/// Whether a header trimmed by [`trim_header`] is enclosed in brackets.
+fn is_header(header: &str) -> bool {
+    matches!(
+        (header.as_bytes().first(), header.as_bytes().last()),
+        (Some(b'['), Some(b']'))
+    )
+}
+
+/// The position of the `;` that starts a trailing comment in `value`, see
+/// [`ParseOptions::inline_comments`]. A `;` inside a struct separates properties,
+/// and one before a line break can't start a trailing comment.
+fn find_inline_comment(value: &str) -> Option<usize> {
+    let mut depth = 0usize;
+    let mut quoted = false;
+    let mut chars = value.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => quoted = !quoted,
+            '\\' if quoted => {
+                chars.next();
+            }
+            _ if quoted => {}
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ';' if depth == 0 => return (!value[i..].contains(['\r', '\n'])).then_some(i),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Split `text` into lines the same way [`Directives::from_text`] does.
+///
+/// Any run of `\r` and `\n` characters terminates a line, so `\r\n`, `\r`, and `\n`
+/// are all accepted as line endings. Note that this means that blank lines
+/// don't produce a span.
+pub fn split_lines(text: &str) -> Vec<Span> {
+    split_lines_inner(text, false)
+}
+
+/// Join the lines of a multiline value, trimming spaces and tabs around it.
+/// Every `\\` continuation and the line break after it are replaced by the same
+/// number of spaces, so positions in the result are only offset by the leading
+/// whitespace. Line breaks not preceded by `\\`, i.e. inside quoted strings, are
+/// kept. Only allocates if there is a line break.
+///
+/// Also returns the trimmed span of the last physical line in `text`.
+pub(crate) fn join_continuations(text: &str) -> (Cow<'_, str>, Span) {
+    let mut reduced = Cow::Borrowed("");
+    let mut part_span = Span(0, text.len());
+
+    while let Some(b' ' | b'\t') = text[part_span].as_bytes().first() {
+        part_span.0 += 1;
+    }
+
+    while let Some(b' ' | b'\t') = text[part_span].as_bytes().last() {
+        part_span.1 -= 1;
+    }
+
+    while let Some(eol) = text[part_span].find(['\r', '\n']) {
+        if eol >= 2 && text.get((part_span.0 + eol - 2)..(part_span.0 + eol)) == Some(r"\\") {
+            let reduced = reduced.to_mut();
+            reduced.push_str(&text[(part_span.0)..(part_span.0 + eol - 2)]);
+            reduced.push_str("  ");
+            part_span.0 += eol;
+
+            while matches!(
+                text[part_span].as_bytes().first(),
+                Some(b'\t' | b'\r' | b'\n')
+            ) {
+                part_span.0 += 1;
+                reduced.push(' ');
+            }
+        } else {
+            // Otherwise, the parser only continues lines inside quoted strings,
+            // where line breaks are part of the value
+            let reduced = reduced.to_mut();
+            reduced.push_str(&text[(part_span.0)..(part_span.0 + eol + 1)]);
+            part_span.0 += eol + 1;
+        }
+    }
+
+    match &mut reduced {
+        Cow::Borrowed(_) => reduced = Cow::Borrowed(&text[part_span]),
+        Cow::Owned(reduced) => reduced.push_str(&text[part_span]),
+    }
+    (reduced, part_span)
+}
+
+/// Like [`split_lines`], but if `keep_blank_lines` is set, every line break
+/// terminates exactly one line, so blank lines produce empty spans.
+fn split_lines_inner(text: &str, keep_blank_lines: bool) -> Vec<Span> {
+    let mut lines = Vec::with_capacity(estimate_line_count(text));
+    let mut remaining = text;
+    let mut offset = 0;
+    while !remaining.is_empty() {
+        match memchr2(b'\r', b'\n', remaining.as_bytes()) {
+            Some(p) => {
+                lines.push(Span(offset, offset + p));
+                offset += p;
+                remaining = &remaining[p..];
+                if keep_blank_lines {
+                    let len = if remaining.starts_with("\r\n") { 2 } else { 1 };
+                    offset += len;
+                    remaining = &remaining[len..];
+                } else {
+                    while remaining.starts_with(['\r', '\n']) {
+                        offset += 1;
+                        remaining = &remaining[1..];
                     }
                 }
             }
-            lines
-        };
+            None => {
+                lines.push(Span(offset, offset + remaining.len()));
+                break;
+            }
+        }
+    }
+    lines
+}
+
+/// A rough guess of the number of lines in `text`, used to size allocations up front.
+fn estimate_line_count(text: &str) -> usize {
+    text.len() / 40
+}
+
+impl<'a> Directives<'a> {
+    /// Parse `text` with the default [`ParseOptions`].
+    pub fn from_text(text: &'a str) -> Self {
+        Self::from_text_with_options(text, &ParseOptions::default())
+    }
+
+    /// Parse `text`. New options are added to [`ParseOptions`] rather than as
+    /// separate constructors.
+    pub fn from_text_with_options(text: &'a str, options: &ParseOptions) -> Self {
+        // Split our input text into lines
+        let lines = split_lines_inner(text, options.keep_blank_lines);
 
         // Then parse directives
         let directives = {
-            let mut directives = vec![];
+            let mut directives = Vec::with_capacity(estimate_line_count(text));
             let mut l_index = 0;
             while l_index < lines.len() {
                 let span = lines[l_index];
                 let line = &text[span];
 
-                if matches!(
-                    (line.as_bytes().first(), line.as_bytes().last()),
-                    (Some(b'['), Some(b']'))
-                ) {
+                let (header_span, comment) = trim_header(text, span);
+                if is_header(&text[header_span]) {
+                    // The header is at least `[]`, so the name is empty at worst
                     directives.push(Directive::SectionHeader(SectionHeader {
                         span,
-                        obj_name: Span(span.0 + 1, span.1 - 1),
+                        trimmed: header_span,
+                        obj_name: Span(header_span.0 + 1, header_span.1 - 1),
+                        comment,
                     }));
                 } else {
                     let mut trim_span = span;
@@ -128,29 +444,95 @@ impl<'a> Directives<'a> {
                         trim_span.0 += 1;
                     }
                     let trim_line = &text[trim_span];
+                    // The first `=` separates key and value, any further `=` are part of the value
                     if let Some(p) = trim_line.find('=') {
-                        let mut prop_span = Span(trim_span.0, trim_span.0 + p);
-                        while let Some(b' ' | b'\t') = text.as_bytes().get(prop_span.1 - 1) {
+                        let op = trim_line.as_bytes()[0].into();
+                        // The KVP starts after the operation character. For `Set`, there is
+                        // none, so a line like `=Value` has an empty key. The key itself
+                        // also skips whitespace after the operation.
+                        let kvp_start = trim_span.0 + usize::from(op != KvpOperation::Set);
+                        let mut key_start = kvp_start;
+                        while key_start < trim_span.0 + p
+                            && matches!(text.as_bytes()[key_start], b' ' | b'\t')
+                        {
+                            key_start += 1;
+                        }
+                        let mut prop_span = Span(key_start, trim_span.0 + p);
+                        while prop_span.1 > prop_span.0
+                            && matches!(text.as_bytes()[prop_span.1 - 1], b' ' | b'\t')
+                        {
                             prop_span.1 -= 1;
                         }
-                        let op = trim_line.as_bytes()[0].into();
                         let mut value_span = Span(trim_span.0 + p + 1, trim_span.1);
 
                         let mut test_line = trim_line;
-                        while test_line.ends_with(r"\\") && l_index < lines.len() - 1 {
-                            l_index += 1;
-                            let next_span = lines[l_index];
-                            test_line = &text[next_span];
-                            value_span.1 = next_span.1;
+                        let mut line_count = 1;
+                        let mut quoted = ends_quoted(&text[value_span], false);
+                        while l_index < lines.len() - 1 {
+                            if !quoted && test_line.ends_with(r"\\") {
+                                // Skip the empty lines that `keep_blank_lines` produces. If
+                                // only those follow, there is nothing to continue with.
+                                l_index = match (l_index + 1..lines.len())
+                                    .find(|&i| lines[i].0 != lines[i].1)
+                                {
+                                    Some(next) => next,
+                                    None => break,
+                                };
+                                line_count += 1;
+                                test_line = &text[lines[l_index]];
+                                quoted = ends_quoted(test_line, false);
+                            } else if quoted && !test_line.ends_with('\\') {
+                                // A quoted string containing line breaks. Backslashes at the
+                                // end of an unterminated string are more likely a path like
+                                // `"C:\\`, so those don't continue. Neither does a string
+                                // that runs into a header or the next KVP.
+                                let mut state = true;
+                                let closing = (l_index + 1..lines.len())
+                                    .take_while(|&i| {
+                                        !starts_kvp(&text[lines[i]])
+                                            && !is_header(&text[trim_header(text, lines[i]).0])
+                                    })
+                                    .find(|&i| {
+                                        state = ends_quoted(&text[lines[i]], state);
+                                        !state
+                                    });
+                                let closing = match closing {
+                                    Some(closing) => closing,
+                                    None => break,
+                                };
+                                line_count += lines[l_index + 1..=closing]
+                                    .iter()
+                                    .filter(|l| l.0 != l.1)
+                                    .count() as u32;
+                                l_index = closing;
+                                test_line = &text[lines[l_index]];
+                                quoted = false;
+                            } else {
+                                break;
+                            }
+                            value_span.1 = lines[l_index].1;
                         }
-                        if op != KvpOperation::Set {
-                            prop_span.0 += 1;
+
+                        let end = value_span.1;
+                        let mut comment = None;
+                        if options.inline_comments {
+                            if let Some(pos) = find_inline_comment(&text[value_span]) {
+                                comment = Some(Span(value_span.0 + pos, end));
+                                value_span.1 = value_span.0 + pos;
+                                while value_span.1 > value_span.0
+                                    && matches!(text.as_bytes()[value_span.1 - 1], b' ' | b'\t')
+                                {
+                                    value_span.1 -= 1;
+                                }
+                            }
                         }
                         directives.push(Directive::Kvp(Kvp {
                             ident: prop_span,
                             op,
-                            span: Span(prop_span.0, value_span.1),
+                            span: Span(kvp_start, end),
                             value: value_span,
+                            comment,
+                            line_count,
                         }));
                     } else if !line
                         .as_bytes()
@@ -159,8 +541,10 @@ impl<'a> Directives<'a> {
                     {
                         directives.push(Directive::Unknown(Unknown {
                             span,
-                            prev_span: l_index.checked_sub(1).and_then(|i| lines.get(i).copied()),
+                            prev_span: lines[..l_index].iter().rev().find(|s| s.0 != s.1).copied(),
                         }));
+                    } else if options.keep_blank_lines {
+                        directives.push(Directive::Blank(span));
                     }
                 }
 
@@ -172,4 +556,838 @@ impl<'a> Directives<'a> {
 
         directives
     }
+
+    /// Parse text that may not be valid UTF-8, like configs saved as Windows-1252.
+    /// Invalid sequences are replaced with U+FFFD before parsing, which changes
+    /// the length of the text, so spans in the result refer to the decoded
+    /// [`OwnedDirectives::text`] rather than to `bytes`.
+    pub fn from_bytes_lossy(bytes: &[u8]) -> OwnedDirectives {
+        let text = String::from_utf8_lossy(bytes).into_owned();
+        let directives = Directives::from_text(&text).directives;
+        OwnedDirectives { text, directives }
+    }
+
+    /// Copy the text so that the result doesn't borrow the source anymore.
+    pub fn into_owned(self) -> OwnedDirectives {
+        OwnedDirectives {
+            text: self.text.to_owned(),
+            directives: self.directives,
+        }
+    }
+
+    /// The number of directives.
+    pub fn len(&self) -> usize {
+        self.directives.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.directives.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Directive> {
+        self.directives.get(index)
+    }
+
+    /// All KVPs in source order.
+    pub fn kvps(&self) -> impl Iterator<Item = &Kvp> + '_ {
+        self.directives.iter().filter_map(|d| match d {
+            Directive::Kvp(k) => Some(k),
+            _ => None,
+        })
+    }
+
+    /// All KVPs with the operation `op` in source order, e.g. every `-` removal.
+    pub fn kvps_with_op(&self, op: KvpOperation) -> impl Iterator<Item = &Kvp> + '_ {
+        self.kvps().filter(move |k| k.op == op)
+    }
+
+    /// All KVPs in source order, together with the header of the section they are
+    /// in, or `None` for KVPs before the first header.
+    pub fn kvps_scoped(&self) -> impl Iterator<Item = (Option<&SectionHeader>, &Kvp)> + '_ {
+        self.directives
+            .iter()
+            .scan(None, |section, d| {
+                Some(match d {
+                    Directive::SectionHeader(h) => {
+                        *section = Some(h);
+                        None
+                    }
+                    Directive::Kvp(k) => Some((*section, k)),
+                    _ => None,
+                })
+            })
+            .flatten()
+    }
+
+    /// Map the lowercase name of every section to the ranges of indices into
+    /// `directives` it owns, each from a header up to the next header. Directives
+    /// before the first header belong to a section with an empty name.
+    ///
+    /// A repeated section has one range per occurrence, in source order.
+    pub fn index_sections(&self) -> HashMap<String, Vec<Range<usize>>> {
+        let mut index: HashMap<String, Vec<_>> = HashMap::new();
+        let mut name = String::new();
+        let mut start = 0;
+        for (i, d) in self.directives.iter().enumerate() {
+            if let Directive::SectionHeader(h) = d {
+                if i > start {
+                    index.entry(name).or_default().push(start..i);
+                }
+                name = self.header_text(h).to_ascii_lowercase();
+                start = i;
+            }
+        }
+        if self.directives.len() > start {
+            index
+                .entry(name)
+                .or_default()
+                .push(start..self.directives.len());
+        }
+        index
+    }
+
+    /// Count the directives by kind. Blank lines are not counted.
+    pub fn stats(&self) -> DirectiveStats {
+        self.directives
+            .iter()
+            .fold(DirectiveStats::default(), |mut stats, d| {
+                match d {
+                    Directive::SectionHeader(_) => stats.sections += 1,
+                    Directive::Kvp(k) if self.is_commented_out(k) => stats.comments += 1,
+                    Directive::Kvp(k) => {
+                        stats.kvps += 1;
+                        if k.line_count > 1 {
+                            stats.multiline_kvps += 1;
+                        }
+                    }
+                    Directive::Unknown(u) if self.text[u.span].trim_start().starts_with(';') => {
+                        stats.comments += 1
+                    }
+                    Directive::Unknown(_) => stats.unknowns += 1,
+                    Directive::Blank(_) => {}
+                }
+                stats
+            })
+    }
+
+    /// Returns the source text of `directive` for display purposes.
+    pub fn display(&self, directive: &Directive) -> impl Display + 'a {
+        self.text_of(directive)
+    }
+
+    /// The source text of `directive`, see [`Directive::span`].
+    pub fn text_of(&self, directive: &Directive) -> &'a str {
+        self.slice(directive.span())
+    }
+
+    /// The key of `kvp`, without the operation.
+    pub fn key_text(&self, kvp: &Kvp) -> &'a str {
+        self.slice(kvp.ident)
+    }
+
+    /// The value of `kvp`, including all continuation lines.
+    pub fn value_text(&self, kvp: &Kvp) -> &'a str {
+        self.slice(kvp.value)
+    }
+
+    /// Whether `kvp` is a commented-out line like `; set Foo=1 to enable`, which
+    /// still parses as a KVP because it contains an `=`.
+    pub fn is_commented_out(&self, kvp: &Kvp) -> bool {
+        let key = self.key_text(kvp);
+        key.starts_with(';') || key.starts_with("//")
+    }
+
+    /// The object name of `header`, without the brackets.
+    pub fn header_text(&self, header: &SectionHeader) -> &'a str {
+        self.slice(header.obj_name)
+    }
+
+    /// Spans of directives from this text always lie on char boundaries. For
+    /// anything else, like a directive from a different text, this returns an
+    /// empty string instead of panicking.
+    fn slice(&self, span: Span) -> &'a str {
+        self.text.get(span.0..span.1).unwrap_or_default()
+    }
+
+    /// Finds the directive whose [`Directive::span`] contains `byte`, including
+    /// the position right after its last character. Directives are in source order,
+    /// so this is a binary search.
+    pub fn directive_at(&self, byte: usize) -> Option<(usize, &Directive)> {
+        let index = self.directives.partition_point(|d| d.span().1 < byte);
+        let directive = self.directives.get(index)?;
+        (directive.span().0 <= byte).then_some((index, directive))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use expect_test::expect;
+
+    use super::{
+        split_lines, Directive, DirectiveStats, Directives, KvpOperation, LineIndex, ParseOptions,
+        Span,
+    };
+
+    #[test]
+    fn mixed_line_endings() {
+        let text = "[A]\r\nB=1\rC=2\n\r\n\nD=3";
+        let expected = expect![[r#"
+            [
+                Span(
+                    0,
+                    3,
+                ),
+                Span(
+                    5,
+                    8,
+                ),
+                Span(
+                    9,
+                    12,
+                ),
+                Span(
+                    16,
+                    19,
+                ),
+            ]
+        "#]];
+        expected.assert_debug_eq(&split_lines(text));
+    }
+
+    #[test]
+    fn lone_cr() {
+        let cr = Directives::from_text("[A.B]\rKey=1\r+Arr=(A=1, \\\\\rB=2)\r; Comment");
+        let lf = Directives::from_text("[A.B]\nKey=1\n+Arr=(A=1, \\\\\nB=2)\n; Comment");
+        assert_eq!(cr.directives.len(), 4);
+        assert_eq!(cr.directives, lf.directives);
+    }
+
+    #[test]
+    fn display() {
+        let text = "[A.B]\n  Key=1\n+Arr=(A=1, \\\\\n  B=2)\n; Comment";
+        let directives = Directives::from_text(text);
+        let rendered = directives
+            .directives
+            .iter()
+            .map(|d| directives.display(d).to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rendered,
+            ["[A.B]", "Key=1", "+Arr=(A=1, \\\\\n  B=2)", "; Comment"]
+        );
+    }
+
+    #[test]
+    fn keep_blank_lines() {
+        let text = "[A.B]\n\nKey=1\n  \n\n+Arr=(A=1, \\\\\n\nB=2)\r\n\r\n; Comment";
+        let options = ParseOptions {
+            keep_blank_lines: true,
+            ..Default::default()
+        };
+        let directives = Directives::from_text_with_options(text, &options);
+        let blank = directives
+            .directives
+            .iter()
+            .filter(|d| matches!(d, Directive::Blank(_)))
+            .count();
+        assert_eq!(blank, 4);
+
+        let rendered = directives
+            .directives
+            .iter()
+            .map(|d| directives.display(d).to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        assert_eq!(rendered, text.replace("\r\n", "\n"));
+
+        // Apart from the blank lines, the result is the same as without the option.
+        let without = Directives::from_text(text);
+        let mut kept = directives.directives;
+        kept.retain(|d| !matches!(d, Directive::Blank(_)));
+        assert_eq!(kept, without.directives);
+    }
+
+    #[test]
+    fn keep_blank_lines_continuation() {
+        let kvps = |text, keep_blank_lines| {
+            let options = ParseOptions {
+                keep_blank_lines,
+                ..Default::default()
+            };
+            Directives::from_text_with_options(text, &options)
+                .kvps()
+                .map(|k| (k.span, k.value, k.line_count))
+                .collect::<Vec<_>>()
+        };
+        for text in [
+            "Key=a \\\\\n\n\n",
+            "Key=a \\\\\n\r\n",
+            "Key=a \\\\\n\nb\nNext=1 \\\\",
+            "Key=\"x\n\ny\" \\\\\n\n",
+        ] {
+            assert_eq!(kvps(text, true), kvps(text, false), "{:?}", text);
+        }
+        assert_eq!(kvps("Key=a \\\\\n\n", true)[0].2, 1);
+    }
+
+    #[test]
+    fn line_index() {
+        let text = "[A]\r\nB=ä\rC=2\n\nD=3";
+        let index = LineIndex::new(text);
+        let positions = [0, 3, 5, 9, 10, 11, 14, 15, text.len()]
+            .iter()
+            .map(|&p| index.line_col(p))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            positions,
+            [
+                (1, 1),
+                (1, 4),
+                (2, 1),
+                (2, 4),
+                (3, 1),
+                (3, 2),
+                (4, 1),
+                (5, 1),
+                (5, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn equals_in_value() {
+        let text = "URL=a=b=c\nKey==x\n+Key = =x\n  =x";
+        let directives = Directives::from_text(text);
+        let kvps = directives
+            .directives
+            .iter()
+            .map(|d| match d {
+                Directive::Kvp(k) => (&text[k.ident], &text[k.value]),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kvps,
+            [("URL", "a=b=c"), ("Key", "=x"), ("Key", " =x"), ("", "x")]
+        );
+    }
+
+    #[test]
+    fn header_comment() {
+        use crate::check::SimpleSyntaxValidator;
+
+        let text = "[A.B] ; note\n[A.C];\n; [A.D]\n[A.E] x ; note";
+        let directives = Directives::from_text(text);
+        let expected = expect![[r#"
+            [
+                SectionHeader(
+                    SectionHeader {
+                        span: Span(
+                            0,
+                            12,
+                        ),
+                        trimmed: Span(
+                            0,
+                            5,
+                        ),
+                        obj_name: Span(
+                            1,
+                            4,
+                        ),
+                        comment: Some(
+                            Span(
+                                6,
+                                12,
+                            ),
+                        ),
+                    },
+                ),
+                SectionHeader(
+                    SectionHeader {
+                        span: Span(
+                            13,
+                            19,
+                        ),
+                        trimmed: Span(
+                            13,
+                            18,
+                        ),
+                        obj_name: Span(
+                            14,
+                            17,
+                        ),
+                        comment: Some(
+                            Span(
+                                18,
+                                19,
+                            ),
+                        ),
+                    },
+                ),
+                Unknown(
+                    Unknown {
+                        span: Span(
+                            20,
+                            27,
+                        ),
+                        prev_span: Some(
+                            Span(
+                                13,
+                                19,
+                            ),
+                        ),
+                    },
+                ),
+                Unknown(
+                    Unknown {
+                        span: Span(
+                            28,
+                            42,
+                        ),
+                        prev_span: Some(
+                            Span(
+                                20,
+                                27,
+                            ),
+                        ),
+                    },
+                ),
+            ]
+        "#]];
+        expected.assert_debug_eq(&directives.directives);
+
+        let errs = Directives::from_text("[A.B] ; note").validate(&SimpleSyntaxValidator);
+        assert!(errs.is_empty());
+    }
+
+    #[test]
+    fn into_owned() {
+        let text = String::from("[A.B]\nKey=1\n+Arr=X");
+        let expected = Directives::from_text(&text).directives;
+        let owned = Directives::from_text(&text).into_owned();
+        drop(text);
+
+        let directives = owned.as_directives();
+        assert_eq!(directives.directives, expected);
+        let keys = directives
+            .directives
+            .iter()
+            .filter_map(|d| match d {
+                Directive::Kvp(k) => Some(&directives.text[k.ident]),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys, ["Key", "Arr"]);
+    }
+
+    #[test]
+    fn line_count() {
+        let text = "Key=1\n+Arr=(A=1, \\\\\n  B=2, \\\\\n  C=3)\n\\\\";
+        let counts = Directives::from_text(text)
+            .directives
+            .iter()
+            .filter_map(|d| match d {
+                Directive::Kvp(k) => Some(k.line_count),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(counts, [1, 3]);
+    }
+
+    #[test]
+    fn len_and_get() {
+        let directives = Directives::from_text("[A.B]\nKey=1\n\n; Comment");
+        assert_eq!(directives.len(), 3);
+        assert!(!directives.is_empty());
+        assert!(matches!(directives.get(1), Some(Directive::Kvp(_))));
+        assert!(directives.get(3).is_none());
+        assert!(Directives::from_text("\n\n").is_empty());
+    }
+
+    #[test]
+    fn directive_at() {
+        let text = "[A.B]\nKey=1\n\n+Arr=(A=1, \\\\\n  B=2)\nLast=1";
+        let directives = Directives::from_text(text);
+        let found = |byte| directives.directive_at(byte).map(|(i, _)| i);
+
+        assert_eq!(found(0), Some(0));
+        assert_eq!(found(text.find("Key").unwrap() + 2), Some(1));
+        // The empty line between `Key` and `+Arr`
+        assert_eq!(found(text.find("\n\n").unwrap() + 1), None);
+        assert_eq!(found(text.find("+Arr").unwrap()), Some(2));
+        assert_eq!(found(text.find("B=2").unwrap()), Some(2));
+        assert_eq!(found(text.len()), Some(3));
+        assert_eq!(found(text.len() + 1), None);
+    }
+
+    #[test]
+    fn key_without_operation() {
+        let text = "=weird\n+Key=x\n+=y\n!  Arr =\n+ Key=x\n- \t=y\nPlain=z";
+        let directives = Directives::from_text(text);
+        let kvps = directives
+            .directives
+            .iter()
+            .map(|d| match d {
+                Directive::Kvp(k) => {
+                    assert!(k.ident.0 <= k.ident.1);
+                    // Only whitespace between the operation and the key
+                    assert_eq!(text[Span(k.span.0, k.ident.0)].trim(), "");
+                    (k.op, &text[k.ident], &text[k.value])
+                }
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kvps,
+            [
+                (KvpOperation::Set, "", "weird"),
+                (KvpOperation::InsertUnique, "Key", "x"),
+                (KvpOperation::InsertUnique, "", "y"),
+                (KvpOperation::Clear, "Arr", ""),
+                (KvpOperation::InsertUnique, "Key", "x"),
+                (KvpOperation::Remove, "", "y"),
+                (KvpOperation::Set, "Plain", "z"),
+            ]
+        );
+    }
+
+    #[test]
+    fn multiline_strings() {
+        let text = "[A.B]\nText=\"First line\nsecond line\"\nNext=(A=\"x\ny\", \\\\\n  B=\"z\")\nOpen=\"never closed\nLast=1";
+        let kvps = Directives::from_text(text)
+            .directives
+            .iter()
+            .filter_map(|d| match d {
+                Directive::Kvp(k) => Some((&text[k.ident], &text[k.value], k.line_count)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kvps,
+            [
+                ("Text", "\"First line\nsecond line\"", 2),
+                ("Next", "(A=\"x\ny\", \\\\\n  B=\"z\")", 3),
+                ("Open", "\"never closed", 1),
+                ("Last", "1", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn inch_marks() {
+        let text = "[A.B]\nSize=5\" monitor\nOther=1\n[C.D]\nDesc=12\" screen\n\
+                    Text=\"runs into\n[E.F]\nArr=(A=\"x\", B=1\" \\\\\n  C=\"y\")\n\
+                    Open=\"runs into\n+Next=\"z\"";
+        let directives = Directives::from_text(text);
+        let kvps = directives
+            .kvps()
+            .map(|k| (&text[k.ident], &text[k.value], k.line_count))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            kvps,
+            [
+                ("Size", "5\" monitor", 1),
+                ("Other", "1", 1),
+                ("Desc", "12\" screen", 1),
+                ("Text", "\"runs into", 1),
+                ("Arr", "(A=\"x\", B=1\" \\\\\n  C=\"y\")", 2),
+                ("Open", "\"runs into", 1),
+                ("Next", "\"z\"", 1),
+            ]
+        );
+        assert_eq!(directives.stats().sections, 3);
+    }
+
+    #[test]
+    fn quoted_backslashes() {
+        let text = "Key=\"C:\\\\\"\nPath=\"C:\\\\\nOther=(A=\"x\", \\\\\n  B=\"y\\\\\")\nLast=1";
+        let keys = Directives::from_text(text)
+            .directives
+            .iter()
+            .map(|d| match d {
+                Directive::Kvp(k) => (&text[k.ident], k.line_count),
+                _ => unreachable!(),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [("Key", 1), ("Path", 1), ("Other", 2), ("Last", 1)]);
+    }
+
+    #[test]
+    fn stats() {
+        let text = "; header comment\nTop=1\n[A.B]\nKey=1\n; set Foo=1 to enable\n+Arr=(A=1, \\\\\n  B=2)\n\n  ; indented\nstray line\n[A.C]\nText=\"a\nb\"";
+        let options = ParseOptions {
+            keep_blank_lines: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            Directives::from_text_with_options(text, &options).stats(),
+            DirectiveStats {
+                sections: 2,
+                kvps: 4,
+                comments: 3,
+                unknowns: 1,
+                multiline_kvps: 2,
+            }
+        );
+    }
+
+    #[test]
+    fn kvps_with_op() {
+        let text = "[A.B]\n-Arr=X\n+Arr=Y\nKey=1\n[A.C]\n-Other=(A=1)\n!Arr=\n-Arr=Z";
+        let directives = Directives::from_text(text);
+        assert_eq!(directives.kvps().count(), 6);
+        let removals = directives
+            .kvps_with_op(KvpOperation::Remove)
+            .map(|k| (&text[k.ident], &text[k.value]))
+            .collect::<Vec<_>>();
+        assert_eq!(removals, [("Arr", "X"), ("Other", "(A=1)"), ("Arr", "Z")]);
+    }
+
+    #[test]
+    fn from_bytes_lossy() {
+        // `é` in Windows-1252
+        let bytes = b"[A.B]\nName=Caf\xe9\n+Arr=(A=1, \\\\\nB=2)\nKey=1";
+        let owned = Directives::from_bytes_lossy(bytes);
+        assert_eq!(owned.directives.len(), 4);
+        let directives = owned.as_directives();
+        let values = directives
+            .kvps()
+            .map(|k| &directives.text[k.value])
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["Caf\u{fffd}", "(A=1, \\\\\nB=2)", "1"]);
+    }
+
+    #[test]
+    fn text_accessors() {
+        let text = "[XComGame.X2Item] ; note\n  +Arr = (A=1, \\\\\n  B=\"é\")\n; Comment";
+        let directives = Directives::from_text(text);
+        let header = match directives.directives[0] {
+            Directive::SectionHeader(h) => h,
+            _ => unreachable!(),
+        };
+        assert_eq!(directives.header_text(&header), "XComGame.X2Item");
+        let kvp = directives.kvps().next().unwrap();
+        assert_eq!(directives.key_text(kvp), &text[kvp.ident]);
+        assert_eq!(directives.key_text(kvp), "Arr");
+        assert_eq!(directives.value_text(kvp), &text[kvp.value]);
+        assert_eq!(directives.value_text(kvp), " (A=1, \\\\\n  B=\"é\")");
+        assert_eq!(
+            directives.text_of(&directives.directives[1]),
+            "+Arr = (A=1, \\\\\n  B=\"é\")"
+        );
+        assert_eq!(directives.text_of(&directives.directives[2]), "; Comment");
+
+        // A directive that doesn't belong to the text
+        let other = Directives::from_text("é");
+        assert_eq!(other.value_text(kvp), "");
+    }
+
+    #[test]
+    fn default_options() {
+        let text = "[A.B]\n\nKey=1\n+Arr=(A=1, \\\\\n\nB=2)\n; Comment\n  \nText=\"a\n\nb\"";
+        assert!(!ParseOptions::default().keep_blank_lines);
+        assert_eq!(
+            Directives::from_text(text).directives,
+            Directives::from_text_with_options(text, &ParseOptions::default()).directives
+        );
+    }
+
+    #[test]
+    fn span_order() {
+        let mut spans = vec![
+            Span(10, 12),
+            Span(3, 9),
+            Span(10, 11),
+            Span(0, 20),
+            Span(3, 4),
+        ];
+        spans.sort();
+        assert_eq!(
+            spans,
+            [
+                Span(0, 20),
+                Span(3, 4),
+                Span(3, 9),
+                Span(10, 11),
+                Span(10, 12)
+            ]
+        );
+
+        let unique = spans
+            .iter()
+            .chain(&spans)
+            .copied()
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(unique.len(), spans.len());
+    }
+
+    #[test]
+    fn kvps_scoped() {
+        let text = "Top=0\n[A.B]\nKey=1\n; Comment\n+Arr=X\n[A.C]\nKey=2";
+        let directives = Directives::from_text(text);
+        let scoped = directives
+            .kvps_scoped()
+            .map(|(h, k)| (h.map(|h| directives.header_text(h)), directives.key_text(k)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            scoped,
+            [
+                (None, "Top"),
+                (Some("A.B"), "Key"),
+                (Some("A.B"), "Arr"),
+                (Some("A.C"), "Key")
+            ]
+        );
+    }
+
+    #[test]
+    fn index_sections() {
+        let text = "Top=0\n[Engine.Engine]\nKey=1\n; Comment\n+Arr=X\n[Engine.Other]\nKey=2\n[engine.engine]\nKey=3";
+        let index = Directives::from_text(text).index_sections();
+        let mut ranges = index.iter().collect::<Vec<_>>();
+        ranges.sort_by_key(|(_, r)| r[0].start);
+        let expected = expect![[r#"
+            [
+                (
+                    "",
+                    [
+                        0..1,
+                    ],
+                ),
+                (
+                    "engine.engine",
+                    [
+                        1..5,
+                        7..9,
+                    ],
+                ),
+                (
+                    "engine.other",
+                    [
+                        5..7,
+                    ],
+                ),
+            ]
+        "#]];
+        expected.assert_debug_eq(&ranges);
+
+        let index = Directives::from_text("[A.B]\nKey=1\n[A.C]").index_sections();
+        assert_eq!(index.len(), 2);
+        assert_eq!(index["a.b"].len(), 1);
+        assert_eq!(index["a.b"][0], 0..2);
+        assert_eq!(index["a.c"].len(), 1);
+        assert_eq!(index["a.c"][0], 2..3);
+        assert!(Directives::from_text("").index_sections().is_empty());
+    }
+
+    #[test]
+    fn inline_comments() {
+        let text = "[A.B]\nKey=3 ; note\nStruct=(A=1; B=\"x;y\") ;ok\nPath=\"C:\\\\a;b\"\n+Arr=(A=1, \\\\\n  B=2)\t; last line\nEmpty=;\n";
+        let options = ParseOptions {
+            inline_comments: true,
+            ..Default::default()
+        };
+        let directives = Directives::from_text_with_options(text, &options);
+        let kvps = directives
+            .kvps()
+            .map(|k| {
+                (
+                    directives.value_text(k),
+                    k.comment.map(|c| &text[c]),
+                    &text[k.span],
+                )
+            })
+            .collect::<Vec<_>>();
+        let expected = expect![[r#"
+            [
+                (
+                    "3",
+                    Some(
+                        "; note",
+                    ),
+                    "Key=3 ; note",
+                ),
+                (
+                    "(A=1; B=\"x;y\")",
+                    Some(
+                        ";ok",
+                    ),
+                    "Struct=(A=1; B=\"x;y\") ;ok",
+                ),
+                (
+                    "\"C:\\\\a;b\"",
+                    None,
+                    "Path=\"C:\\\\a;b\"",
+                ),
+                (
+                    "(A=1, \\\\\n  B=2)",
+                    Some(
+                        "; last line",
+                    ),
+                    "Arr=(A=1, \\\\\n  B=2)\t; last line",
+                ),
+                (
+                    "",
+                    Some(
+                        ";",
+                    ),
+                    "Empty=;",
+                ),
+            ]
+        "#]];
+        expected.assert_debug_eq(&kvps);
+        assert_eq!(
+            directives.resolved_value("A.B", "Key"),
+            Some(crate::eval::ResolvedValue::Scalar("3"))
+        );
+
+        // Formatting keeps the comments
+        let formatted = directives.format(Default::default());
+        assert!(formatted.contains("Key=3 ; note\n"));
+        assert!(formatted.contains("    B=2) ; last line\n"));
+
+        let plain = Directives::from_text(text);
+        assert_eq!(plain.value_text(plain.kvps().next().unwrap()), "3 ; note");
+        assert!(plain.kvps().all(|k| k.comment.is_none()));
+    }
+
+    #[test]
+    fn directive_span() {
+        let text = "[A.B] ; header\n\n  Key = 1 \n+Arr=(A=1, \\\\\nB=2) ; c\n// unknown";
+        let options = ParseOptions {
+            keep_blank_lines: true,
+            inline_comments: true,
+        };
+        let directives = Directives::from_text_with_options(text, &options);
+        let spans = directives
+            .directives
+            .iter()
+            .map(|d| &text[d.span()])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            spans,
+            [
+                "[A.B] ; header",
+                "",
+                "Key = 1 ",
+                "+Arr=(A=1, \\\\\nB=2) ; c",
+                "// unknown"
+            ]
+        );
+    }
+
+    #[test]
+    fn logical_value() {
+        let text = "[A.B]\n+Arr=(A=1, \\\\\r\n\tB=2)\nKey= Single \nText=\"a\nb\"";
+        let directives = Directives::from_text(text);
+        let values = directives
+            .kvps()
+            .map(|k| k.logical_value(text))
+            .collect::<Vec<_>>();
+        assert_eq!(values, ["(A=1,      B=2)", "Single", "\"a\nb\""]);
+        assert!(matches!(values[1], Cow::Borrowed(_)));
+        assert!(matches!(values[0], Cow::Owned(_)));
+    }
 }
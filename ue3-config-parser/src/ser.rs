@@ -0,0 +1,394 @@
+//! Serialize `serde` types as struct values.
+
+use std::fmt;
+
+use serde::ser::{self, Serialize, Serializer};
+
+use crate::check::escape_value;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error(String);
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error(msg.to_string())
+    }
+}
+
+/// Serialize `value` as a struct value, the inverse of [`crate::de::from_struct`].
+///
+/// Structs and maps become `(Field=..., Field=...)`, sequences and tuples become
+/// `(a,b,c)`, and strings are quoted with [`escape_value`] if needed. Fields that
+/// are `None` are left out.
+///
+/// ```
+/// use serde::Serialize;
+/// use ue3_config_parser::ser::to_struct_string;
+///
+/// #[derive(Serialize)]
+/// struct Cost {
+///     #[serde(rename = "ItemTemplateName")]
+///     name: &'static str,
+///     #[serde(rename = "Quantity")]
+///     quantity: u32,
+/// }
+///
+/// let text = to_struct_string(&Cost { name: "Supplies", quantity: 25 }).unwrap();
+/// assert_eq!(text, "(ItemTemplateName=Supplies, Quantity=25)");
+/// ```
+pub fn to_struct_string<T: Serialize + ?Sized>(value: &T) -> Result<String, Error> {
+    value
+        .serialize(ValueSerializer)
+        .and_then(|v| v.ok_or_else(|| Error("expected a value".to_owned())))
+}
+
+fn unsupported(what: &str) -> Error {
+    Error(format!("{} can't be represented in a struct value", what))
+}
+
+/// Serializes a single value. `None` and `()` serialize to `None`, so that
+/// struct fields can be left out.
+struct ValueSerializer;
+
+impl Serializer for ValueSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = ser::Impossible<Option<String>, Error>;
+    type SerializeMap = StructSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = ser::Impossible<Option<String>, Error>;
+
+    fn serialize_bool(self, v: bool) -> Result<Option<String>, Error> {
+        Ok(Some(if v { "True" } else { "False" }.to_owned()))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Option<String>, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Option<String>, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Option<String>, Error> {
+        self.serialize_i64(v.into())
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Option<String>, Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Option<String>, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Option<String>, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Option<String>, Error> {
+        self.serialize_u64(v.into())
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Option<String>, Error> {
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Option<String>, Error> {
+        self.serialize_f64(v.into())
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Option<String>, Error> {
+        if !v.is_finite() {
+            return Err(unsupported("a non-finite number"));
+        }
+        Ok(Some(v.to_string()))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Option<String>, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Option<String>, Error> {
+        Ok(Some(escape_value(v).into_owned()))
+    }
+
+    fn serialize_bytes(self, _v: &[u8]) -> Result<Option<String>, Error> {
+        Err(unsupported("a byte array"))
+    }
+
+    fn serialize_none(self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Option<String>, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Option<String>, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Option<String>, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<Option<String>, Error> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, Error> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, Error> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(unsupported("an enum variant with data"))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<StructSerializer, Error> {
+        Ok(StructSerializer {
+            fields: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<StructSerializer, Error> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(unsupported("an enum variant with data"))
+    }
+}
+
+struct SeqSerializer(Vec<String>);
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        match value.serialize(ValueSerializer)? {
+            Some(v) => self.0.push(v),
+            None => return Err(unsupported("an array element without a value")),
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<String>, Error> {
+        Ok(Some(format!("({})", self.0.join(","))))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<String>, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<String>, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<String>, Error> {
+        self.finish()
+    }
+}
+
+struct StructSerializer {
+    fields: Vec<String>,
+    /// The key of a map entry whose value hasn't been serialized yet.
+    key: Option<String>,
+}
+
+impl StructSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        if let Some(v) = value.serialize(ValueSerializer)? {
+            self.fields.push(format!("{}={}", key, v));
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<String>, Error> {
+        Ok(Some(format!("({})", self.fields.join(", "))))
+    }
+}
+
+impl ser::SerializeMap for StructSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        match key.serialize(ValueSerializer)? {
+            Some(k) if !k.starts_with(['"', '(']) => self.key = Some(k),
+            _ => return Err(unsupported("a map key that isn't a name")),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().expect("value serialized before key");
+        self.push(&key, value)
+    }
+
+    fn end(self) -> Result<Option<String>, Error> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Option<String>;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Option<String>, Error> {
+        self.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::to_struct_string;
+    use crate::{de::from_value, parse_value};
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Weapon {
+        name: String,
+        damage: (i32, f64),
+        tags: Vec<String>,
+        ammo: Option<u32>,
+        upgrade: Option<Upgrade>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    #[serde(rename_all = "PascalCase")]
+    struct Upgrade {
+        slot: Slot,
+        enabled: bool,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    enum Slot {
+        Primary,
+        Secondary,
+    }
+
+    #[test]
+    fn round_trip() {
+        let weapon = Weapon {
+            name: r#"Laser "Mk2""#.to_owned(),
+            damage: (-3, 1.5),
+            tags: vec!["Energy".to_owned(), "Heavy, Slow".to_owned()],
+            ammo: None,
+            upgrade: Some(Upgrade {
+                slot: Slot::Secondary,
+                enabled: true,
+            }),
+        };
+        let text = to_struct_string(&weapon).unwrap();
+        assert_eq!(
+            text,
+            r#"(Name="Laser \"Mk2\"", Damage=(-3,1.5), Tags=(Energy,"Heavy, Slow"), Upgrade=(Slot=Secondary, Enabled=True))"#
+        );
+
+        let parsed = parse_value(&text).unwrap();
+        let read: Weapon = from_value(&parsed).unwrap();
+        assert_eq!(read, weapon);
+    }
+
+    #[test]
+    fn unsupported() {
+        assert!(to_struct_string(&vec![Some(1), None]).is_err());
+        assert!(to_struct_string(&f64::NAN).is_err());
+        assert_eq!(to_struct_string(&Vec::<u8>::new()).unwrap(), "()");
+    }
+}
@@ -0,0 +1,78 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+use expect_test::expect;
+
+fn lint(args: &[&str], stdin: Option<&str>) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_ue3-config-lint"))
+        .current_dir(concat!(env!("CARGO_MANIFEST_DIR"), "/test_data/lint"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.unwrap_or_default().as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn clean_directory() {
+    let output = lint(&["ok"], None);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn fixture_directory() {
+    let output = lint(&["."], None);
+    assert_eq!(output.status.code(), Some(1));
+    // Every check has its own code
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("[custom]"));
+    let expected = expect![[r#"
+        ./bad/XComGame.ini:1:1: error[malformed-header]: Invalid header. The first character of a header line must be `[` and the last must be `]`.
+        ./bad/XComGame.ini:2:1: error[slash-comment]: UnrealScript-style comment (please use `;`)
+        ./bad/XComGame.ini:3:18: error[unbalanced-parens]: unbalanced parentheses in value
+    "#]];
+    expected.assert_eq(&String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn stdin() {
+    let output = lint(&["-"], Some("[Engine.Engine]\nA=1\nB=(C=)\n"));
+    assert_eq!(output.status.code(), Some(1));
+    let expected = expect![[r#"
+        <stdin>:3:6: error[struct-syntax]: Expected `(` or value
+    "#]];
+    expected.assert_eq(&String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn json() {
+    let output = lint(&["--format", "json", "-"], Some("[Engine.Engine] \nA=1\n"));
+    assert_eq!(output.status.code(), Some(1));
+    let expected = expect![[r#"
+        [
+          {"file":"<stdin>","line":1,"col":1,"end_line":1,"end_col":17,"code":"malformed-header","severity":"error","message":"Invalid header. The first character of a header line must be `[` and the last must be `]`."}
+        ]
+    "#]];
+    expected.assert_eq(&String::from_utf8(output.stdout).unwrap());
+}
+
+#[test]
+fn warnings_as_errors() {
+    let input = "[Engine.Engine]\nKey=\"x\" y\n";
+    let output = lint(&["-"], Some(input));
+    assert!(output.status.success());
+    let expected = expect![[r#"
+        <stdin>:2:9: warning[text-after-quote]: unexpected text after quoted value
+    "#]];
+    expected.assert_eq(&String::from_utf8(output.stdout).unwrap());
+
+    let output = lint(&["--warnings-as-errors", "-"], Some(input));
+    assert_eq!(output.status.code(), Some(1));
+}
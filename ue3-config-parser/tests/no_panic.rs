@@ -0,0 +1,45 @@
+//! Parsing and validating arbitrary text must never panic, no matter how broken
+//! the input is. Byte offsets at the edges of lines, brackets, and multi-byte
+//! characters are easy to get wrong, so these generate text that is dense in them.
+
+use proptest::prelude::*;
+use ue3_config_parser::{
+    check::SimpleSyntaxValidator,
+    parse::{Directives, ParseOptions},
+};
+
+/// Text made mostly of the characters the parser and validators treat specially.
+const CONFIG_CHARS: &str = "[\\[\\]()=+.!;,\"\\\\/ \t\r\naZ_0é€\u{FFFD}-]{0,100}";
+
+fn check(text: &str) {
+    for &(keep_blank_lines, inline_comments) in &[(false, false), (true, true)] {
+        let options = ParseOptions {
+            keep_blank_lines,
+            inline_comments,
+        };
+        let directives = Directives::from_text_with_options(text, &options);
+        for e in directives.validate(&SimpleSyntaxValidator) {
+            // Reported spans must be valid for slicing the text
+            let _ = &text[e.span];
+        }
+        directives.evaluate();
+        directives.format(Default::default());
+    }
+}
+
+proptest! {
+    #[test]
+    fn arbitrary_text(text in any::<String>()) {
+        check(&text);
+    }
+
+    #[test]
+    fn config_like_text(text in CONFIG_CHARS) {
+        check(&text);
+    }
+
+    #[test]
+    fn config_like_lines(lines in prop::collection::vec(CONFIG_CHARS, 0..8)) {
+        check(&lines.join("\n"));
+    }
+}
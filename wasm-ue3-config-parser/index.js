@@ -72,7 +72,8 @@ rust.then(wasm => {
 		for (var annot of annots) {
 			markers.push({
 				startLineNumber: annot.line, startColumn: annot.col, endLineNumber: annot.eline, endColumn: annot.ecol,
-				severity: monaco.MarkerSeverity.Error, message: annot.err
+				severity: annot.severity == "warning" ? monaco.MarkerSeverity.Warning : monaco.MarkerSeverity.Error,
+				message: annot.err, code: annot.code
 			});
 
 			var range = new monaco.Range(annot.line, annot.col, annot.eline, annot.ecol);
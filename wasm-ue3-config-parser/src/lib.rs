@@ -1,9 +1,14 @@
+use std::borrow::Cow;
+
 use serde::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
 
 use ue3_config_parser::{
-    check::{ErrorKind, SimpleSyntaxValidator},
-    parse::Directives,
+    check::{
+        CompositeValidator, DuplicateKeyValidator, SimpleSyntaxValidator, Validator,
+        WhitespaceValidator,
+    },
+    parse::{Directive, Directives, Span},
 };
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -14,39 +19,98 @@ pub struct Annotations {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Annotation {
     pub err: String,
+    pub code: String,
+    pub severity: String,
     pub line: u32,
     pub col: u32,
     pub eline: u32,
     pub ecol: u32,
 }
 
+/// Selects the validators `check_with` runs.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CheckOptions {
+    pub syntax: bool,
+    pub whitespace: bool,
+    pub duplicate_keys: bool,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        Self {
+            syntax: true,
+            whitespace: false,
+            duplicate_keys: false,
+        }
+    }
+}
+
+/// Returns the [`Annotations`] as a plain JS object.
 #[wasm_bindgen]
-pub fn check(input: &str) -> JsValue {
-    JsValue::from_serde(&check_inner(input)).unwrap()
+pub fn check(input: &str) -> Result<JsValue, JsValue> {
+    Ok(serde_wasm_bindgen::to_value(&check_inner(input))?)
+}
+
+/// Like [`check`], with a [`CheckOptions`] object selecting the validators.
+#[wasm_bindgen]
+pub fn check_with(input: &str, options: JsValue) -> Result<JsValue, JsValue> {
+    let options = serde_wasm_bindgen::from_value(options)?;
+    Ok(serde_wasm_bindgen::to_value(&check_with_inner(
+        input, &options,
+    ))?)
 }
 
 fn check_inner(input: &str) -> Annotations {
+    annotate(input, &SimpleSyntaxValidator)
+}
+
+fn check_with_inner(input: &str, options: &CheckOptions) -> Annotations {
+    let mut validator = CompositeValidator::new();
+    if options.syntax {
+        validator = validator.with(SimpleSyntaxValidator);
+    }
+    if options.whitespace {
+        validator = validator.with(WhitespaceValidator);
+    }
+    if options.duplicate_keys {
+        validator = validator.with(DuplicateKeyValidator::new());
+    }
+    annotate(input, &validator)
+}
+
+/// `LineColLookup` only recognizes `\n` as a line break, so replace
+/// lone `\r` with `\n`. This doesn't change any byte offsets.
+fn normalize_line_breaks(input: &str) -> Cow<'_, str> {
+    let bytes = input.as_bytes();
+    let is_lone_cr = |i: usize| bytes[i] == b'\r' && bytes.get(i + 1) != Some(&b'\n');
+    if !(0..bytes.len()).any(is_lone_cr) {
+        return Cow::Borrowed(input);
+    }
+
+    let normalized = (0..bytes.len())
+        .map(|i| if is_lone_cr(i) { b'\n' } else { bytes[i] })
+        .collect::<Vec<u8>>();
+    Cow::Owned(String::from_utf8(normalized).unwrap())
+}
+
+fn annotate(input: &str, validator: &dyn Validator) -> Annotations {
     let directives = Directives::from_text(input);
-    let errors = directives.validate(&SimpleSyntaxValidator);
+    let errors = directives.validate(validator);
 
-    let lookup = line_col::LineColLookup::new(input);
+    let lookup_text = normalize_line_breaks(input);
+    let lookup = line_col::LineColLookup::new(&lookup_text);
     let mut annots = vec![];
 
     for e in errors {
         let (line, col) = lookup.get_by_cluster(e.span.0);
         let (eline, ecol) = lookup.get_by_cluster(e.span.1);
-        let err = match &e.kind {
-            ErrorKind::InvalidIdent => "Invalid identifier",
-            ErrorKind::MalformedHeader => "Invalid header. The first character of a header line must be `[` and the last must be `]`.",
-            ErrorKind::SpaceAfterMultiline => "Unrecognized directive (space after backslashes)",
-            ErrorKind::SlashSlashComent => "UnrealScript-style comment (please use `;`)",
-            ErrorKind::BadValue => "Bad Value",
-            ErrorKind::Custom(s) => s,
-            ErrorKind::Other => "Invalid config directive",
-        };
+        let err = e.kind.message();
 
         annots.push(Annotation {
             err: err.into(),
+            code: e.kind.code().into(),
+            severity: e.severity.as_str().into(),
             line: line as u32,
             col: col as u32,
             eline: eline as u32,
@@ -59,6 +123,93 @@ fn check_inner(input: &str) -> Annotations {
     }
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ParsedDirectives {
+    pub directives: Box<[ParsedDirective]>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum ParsedDirective {
+    SectionHeader {
+        span: Location,
+        obj_name: Location,
+    },
+    Kvp {
+        span: Location,
+        op: String,
+        key: Location,
+        value: Location,
+    },
+    Unknown {
+        span: Location,
+    },
+    Blank {
+        span: Location,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Location {
+    pub start: u32,
+    pub end: u32,
+    pub line: u32,
+    pub col: u32,
+    pub eline: u32,
+    pub ecol: u32,
+}
+
+#[wasm_bindgen]
+pub fn parse(input: &str) -> Result<JsValue, JsValue> {
+    Ok(serde_wasm_bindgen::to_value(&parse_inner(input))?)
+}
+
+fn parse_inner(input: &str) -> ParsedDirectives {
+    let directives = Directives::from_text(input);
+    let lookup_text = normalize_line_breaks(input);
+    let lookup = line_col::LineColLookup::new(&lookup_text);
+    let location = |span: Span| {
+        let (line, col) = lookup.get_by_cluster(span.0);
+        let (eline, ecol) = lookup.get_by_cluster(span.1);
+        Location {
+            start: span.0 as u32,
+            end: span.1 as u32,
+            line: line as u32,
+            col: col as u32,
+            eline: eline as u32,
+            ecol: ecol as u32,
+        }
+    };
+
+    let directives = directives
+        .directives
+        .iter()
+        .map(|d| match d {
+            Directive::SectionHeader(h) => ParsedDirective::SectionHeader {
+                span: location(h.span),
+                obj_name: location(h.obj_name),
+            },
+            Directive::Kvp(k) => ParsedDirective::Kvp {
+                // Includes the operation
+                span: location(d.span()),
+                op: format!("{:?}", k.op),
+                key: location(k.ident),
+                value: location(k.value),
+            },
+            Directive::Unknown(u) => ParsedDirective::Unknown {
+                span: location(u.span),
+            },
+            Directive::Blank(span) => ParsedDirective::Blank {
+                span: location(*span),
+            },
+        })
+        .collect::<Vec<_>>();
+
+    ParsedDirectives {
+        directives: directives.into_boxed_slice(),
+    }
+}
+
 #[wasm_bindgen]
 pub fn init() {
     // When the `console_error_panic_hook` feature is enabled, we can call the
@@ -100,6 +251,8 @@ mod test {
                 annots: [
                     Annotation {
                         err: "Trailing \\\\ without following line",
+                        code: "trailing-continuation",
+                        severity: "error",
                         line: 4,
                         col: 1,
                         eline: 4,
@@ -110,4 +263,103 @@ mod test {
         "#]];
         expected.assert_debug_eq(&super::check_inner(input));
     }
+
+    #[test]
+    fn test_cr() {
+        let input = "[Engine.GameEngine]\r// Comment\r\n// Comment";
+        let lines = super::check_inner(input)
+            .annots
+            .iter()
+            .map(|a| (a.line, a.col, a.eline, a.ecol))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, [(2, 1, 2, 11), (3, 1, 3, 11)]);
+    }
+
+    #[test]
+    fn test_code() {
+        let annots = super::check_inner("[Engine.GameEngine]\n// Comment");
+        assert_eq!(annots.annots.len(), 1);
+        assert_eq!(annots.annots[0].code, "slash-comment");
+        assert_eq!(annots.annots[0].severity, "error");
+    }
+
+    #[test]
+    fn test_check_with() {
+        let input = "[Engine.GameEngine]\nbSmoothFrameRate=true\nbSmoothFrameRate=false";
+        let expected = expect![[r#"
+            Annotations {
+                annots: [],
+            }
+        "#]];
+        expected.assert_debug_eq(&super::check_inner(input));
+
+        let options = super::CheckOptions {
+            duplicate_keys: true,
+            ..Default::default()
+        };
+        let expected = expect![[r#"
+            Annotations {
+                annots: [
+                    Annotation {
+                        err: "duplicate key `bSmoothFrameRate`",
+                        code: "duplicate-key",
+                        severity: "warning",
+                        line: 3,
+                        col: 1,
+                        eline: 3,
+                        ecol: 17,
+                    },
+                ],
+            }
+        "#]];
+        expected.assert_debug_eq(&super::check_with_inner(input, &options));
+    }
+
+    #[test]
+    fn test_parse() {
+        let input = "[Engine.GameEngine]\n+Items=(A=1)\n??";
+        let parsed = super::parse_inner(input);
+        let kinds = parsed
+            .directives
+            .iter()
+            .map(|d| match d {
+                super::ParsedDirective::SectionHeader { .. } => "SectionHeader",
+                super::ParsedDirective::Kvp { .. } => "Kvp",
+                super::ParsedDirective::Unknown { .. } => "Unknown",
+                super::ParsedDirective::Blank { .. } => "Blank",
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, ["SectionHeader", "Kvp", "Unknown"]);
+
+        let expected = expect![[r#"
+            Kvp {
+                span: Location {
+                    start: 20,
+                    end: 32,
+                    line: 2,
+                    col: 1,
+                    eline: 2,
+                    ecol: 13,
+                },
+                op: "InsertUnique",
+                key: Location {
+                    start: 21,
+                    end: 26,
+                    line: 2,
+                    col: 2,
+                    eline: 2,
+                    ecol: 7,
+                },
+                value: Location {
+                    start: 27,
+                    end: 32,
+                    line: 2,
+                    col: 8,
+                    eline: 2,
+                    ecol: 13,
+                },
+            }
+        "#]];
+        expected.assert_debug_eq(&parsed.directives[1]);
+    }
 }
@@ -0,0 +1,52 @@
+//! Tests of the exported functions, which need a JS environment to run in:
+//! `wasm-pack test --node`
+#![cfg(target_arch = "wasm32")]
+
+use js_sys::{Array, Reflect};
+use wasm_bindgen::JsValue;
+use wasm_bindgen_test::wasm_bindgen_test;
+
+fn annots(value: &JsValue) -> Array {
+    Array::from(&Reflect::get(value, &"annots".into()).unwrap())
+}
+
+fn get(value: &JsValue, key: &str) -> JsValue {
+    Reflect::get(value, &key.into()).unwrap()
+}
+
+#[wasm_bindgen_test]
+fn check() {
+    let value = wasm_ue3_config_parser::check("[Engine.GameEngine]\n// Comment\n??").unwrap();
+    let annots = annots(&value);
+    assert_eq!(annots.length(), 2);
+    assert_eq!(get(&annots.get(0), "code").as_string().unwrap(), "slash-comment");
+    assert_eq!(get(&annots.get(1), "code").as_string().unwrap(), "other");
+}
+
+#[wasm_bindgen_test]
+fn parse() {
+    let value = wasm_ue3_config_parser::parse("[Engine.GameEngine]\n+Items=(A=1)\n??").unwrap();
+    let directives = Array::from(&get(&value, "directives"));
+    let kinds = directives
+        .iter()
+        .map(|d| get(&d, "kind").as_string().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(kinds, ["SectionHeader", "Kvp", "Unknown"]);
+
+    // The span of a KVP includes its operation, the key doesn't
+    let kvp = directives.get(1);
+    assert_eq!(get(&get(&kvp, "span"), "start").as_f64(), Some(20.0));
+    assert_eq!(get(&get(&kvp, "key"), "start").as_f64(), Some(21.0));
+    assert_eq!(get(&kvp, "op").as_string().unwrap(), "InsertUnique");
+}
+
+#[wasm_bindgen_test]
+fn check_with() {
+    let input = "[Engine.GameEngine]\nKey=1\nKey=2";
+    let options = js_sys::Object::new();
+    Reflect::set(&options, &"duplicate_keys".into(), &true.into()).unwrap();
+    let value = wasm_ue3_config_parser::check_with(input, options.into()).unwrap();
+    assert_eq!(annots(&value).length(), 1);
+
+    assert!(wasm_ue3_config_parser::check_with(input, "not options".into()).is_err());
+}